@@ -4,3 +4,9 @@ CREATE TABLE public.user (
   name TEXT NOT NULL,
   email TEXT NOT NULL
 );
+
+CREATE TABLE public.account (
+  id UUID CONSTRAINT account_pk PRIMARY KEY,
+  balance BIGINT NOT NULL,
+  version BIGINT NOT NULL DEFAULT 0
+);
@@ -0,0 +1,164 @@
+use std::time::Duration;
+
+use abstract_db_access::{
+    fault::{Fault, FaultInjector, FaultPoint, FaultyUnit},
+    mock::{MockConnection, MockDb, MockUnit},
+    DbAccess, DbUnit, RepositoryError, SavePoint, TransactionUnit,
+};
+use async_trait::async_trait;
+
+#[derive(Debug, Clone, PartialEq)]
+struct User {
+    id: uuid::Uuid,
+    name: String,
+    email: String,
+}
+
+const USER_TABLE: &str = "public.user";
+const MAX_ATTEMPTS: u32 = 3;
+
+#[async_trait]
+trait UserRepository: DbAccess {
+    async fn insert(&mut self, user: User) -> Result<(), RepositoryError>;
+    async fn count(&self) -> Result<usize, RepositoryError>;
+}
+
+#[async_trait]
+impl<T: MockConnection + DbAccess + Send + Sync> UserRepository for T {
+    async fn insert(&mut self, user: User) -> Result<(), RepositoryError> {
+        self.write(|tables| tables.rows_mut(USER_TABLE).push(user));
+        Ok(())
+    }
+
+    async fn count(&self) -> Result<usize, RepositoryError> {
+        Ok(self.read(|tables| tables.rows::<User>(USER_TABLE).len()))
+    }
+}
+
+/// Inserts the user, retrying the transaction on serialization failures
+async fn insert_with_retry(
+    unit: &mut FaultyUnit<MockUnit>,
+    user: User,
+) -> Result<u32, RepositoryError> {
+    let mut attempt = 0;
+    loop {
+        attempt += 1;
+
+        let mut trx = unit.transaction().await?;
+        UserRepository::insert(&mut *trx, user.clone()).await?;
+
+        match trx.commit().await {
+            Ok(()) => return Ok(attempt),
+            Err(RepositoryError::SerializationFailure(_)) if attempt < MAX_ATTEMPTS => continue,
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+async fn retried_commit(db: &MockDb, user: User) -> Result<(), RepositoryError> {
+    let injector = FaultInjector::new().script(
+        FaultPoint::Commit,
+        [
+            Some(Fault::SerializationFailure),
+            Some(Fault::Latency(Duration::from_millis(10))),
+        ],
+    );
+    let mut unit = injector.wrap(db.unit());
+
+    let attempts = insert_with_retry(&mut unit, user).await.unwrap();
+
+    assert_eq!(attempts, 2);
+    assert_eq!(unit.count().await.unwrap(), 1);
+    assert_eq!(injector.injected().len(), 2);
+
+    Ok(())
+}
+
+async fn exhausted_retries(db: &MockDb, user: User) -> Result<(), RepositoryError> {
+    let injector =
+        FaultInjector::new().probability(FaultPoint::Commit, Fault::SerializationFailure, 1.0);
+    let mut unit = injector.wrap(db.unit());
+
+    let res = insert_with_retry(&mut unit, user).await;
+
+    assert!(matches!(res, Err(RepositoryError::SerializationFailure(_))));
+    assert_eq!(injector.injected().len(), MAX_ATTEMPTS as usize);
+    assert_eq!(unit.count().await.unwrap(), 1);
+
+    Ok(())
+}
+
+async fn lost_connection(db: &MockDb, user: User) -> Result<(), RepositoryError> {
+    let injector =
+        FaultInjector::new().script(FaultPoint::SavePoint, [Some(Fault::ConnectionLost)]);
+    let mut unit = injector.wrap(db.unit());
+
+    let mut trx = unit.transaction().await.unwrap();
+    UserRepository::insert(&mut *trx, user).await.unwrap();
+
+    let save_point = trx.save_point("lost").await;
+    assert!(matches!(save_point, Err(RepositoryError::ConnectionClosed)));
+    assert!(trx.is_lost());
+
+    let res = trx.commit().await;
+    assert!(matches!(res, Err(RepositoryError::ConnectionClosed)));
+    assert_eq!(unit.count().await.unwrap(), 1);
+
+    Ok(())
+}
+
+async fn dropped_between_statements(db: &MockDb, users: [User; 2]) -> Result<(), RepositoryError> {
+    let [first, second] = users;
+    let injector =
+        FaultInjector::new().script(FaultPoint::Statement, [None, Some(Fault::ConnectionLost)]);
+    let mut unit = injector.wrap(db.unit());
+
+    let mut trx = unit.transaction().await.unwrap();
+    UserRepository::insert(trx.statement().await.unwrap(), first)
+        .await
+        .unwrap();
+
+    let res = async { UserRepository::insert(trx.statement().await?, second).await }.await;
+    assert!(matches!(res, Err(RepositoryError::ConnectionClosed)));
+    assert!(trx.is_lost());
+
+    // the first statement is rolled back along with the transaction
+    let res = trx.commit().await;
+    assert!(matches!(res, Err(RepositoryError::ConnectionClosed)));
+    assert_eq!(unit.count().await.unwrap(), 1);
+
+    Ok(())
+}
+
+async fn failed_begin(db: &MockDb) -> Result<(), RepositoryError> {
+    let injector = FaultInjector::new().script(FaultPoint::Begin, [Some(Fault::ConnectionLost)]);
+    let mut unit = injector.wrap(db.unit());
+
+    let res = unit.transaction().await;
+    assert!(matches!(res, Err(RepositoryError::ConnectionClosed)));
+
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() {
+    let mut users = (0..).map(|idx| User {
+        id: uuid::Uuid::new_v4(),
+        email: format!("rustac{idx}@email.com"),
+        name: format!("Rustacean {idx}"),
+    });
+
+    let db = MockDb::new();
+
+    retried_commit(&db, users.next().unwrap()).await.unwrap();
+
+    exhausted_retries(&db, users.next().unwrap()).await.unwrap();
+
+    lost_connection(&db, users.next().unwrap()).await.unwrap();
+
+    dropped_between_statements(&db, [users.next().unwrap(), users.next().unwrap()])
+        .await
+        .unwrap();
+
+    failed_begin(&db).await.unwrap();
+}
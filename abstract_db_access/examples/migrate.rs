@@ -0,0 +1,118 @@
+use abstract_db_access::{
+    migrate::{Migration, MigrationError, MigrationState, MigrationStatus, Migrator},
+    migration, RepositoryError,
+};
+use utilities::test_database::{with_test_database, TestDatabase};
+
+static MIGRATIONS: &[Migration] = &[
+    migration!(1, "create_user", "migrations/0001_create_user"),
+    migration!(
+        2,
+        "add_user_created_at",
+        "migrations/0002_add_user_created_at"
+    ),
+];
+
+fn states(status: &[MigrationStatus]) -> Vec<MigrationState> {
+    status.iter().map(|status| status.state).collect()
+}
+
+async fn up_and_down(db: TestDatabase) -> Result<(), RepositoryError> {
+    let pool = db.create_pg_deadpool();
+    let mut unit = pool.get().await.unwrap();
+    let migrator = Migrator::new(MIGRATIONS.iter().cloned())?;
+
+    let status = migrator.status(&mut unit).await?;
+    assert_eq!(
+        states(&status),
+        [MigrationState::Pending, MigrationState::Pending]
+    );
+
+    assert_eq!(migrator.up(&mut unit).await?, [1, 2]);
+    assert!(migrator.up(&mut unit).await?.is_empty());
+    migrator.verify(&mut unit).await?;
+
+    unit.execute("SELECT created_at FROM public.user", &[])
+        .await
+        .unwrap();
+
+    assert_eq!(migrator.down(&mut unit, 1).await?, [2]);
+    let status = migrator.status(&mut unit).await?;
+    assert_eq!(
+        states(&status),
+        [MigrationState::Applied, MigrationState::Pending]
+    );
+
+    Ok(())
+}
+
+async fn concurrent_runners(db: TestDatabase) -> Result<(), RepositoryError> {
+    let pool = db.create_pg_deadpool();
+    let mut first = pool.get().await.unwrap();
+    let mut second = pool.get().await.unwrap();
+    let migrator = Migrator::new(MIGRATIONS.iter().cloned())?;
+
+    // the advisory lock makes the runners take turns, the second one finding nothing to apply
+    let (first, second) = tokio::join!(migrator.up(&mut first), migrator.up(&mut second));
+    let mut applied = [first?, second?].concat();
+    applied.sort();
+    assert_eq!(applied, [1, 2]);
+
+    Ok(())
+}
+
+async fn tampered_migrations(db: TestDatabase) -> Result<(), RepositoryError> {
+    let pool = db.create_pg_deadpool();
+    let mut unit = pool.get().await.unwrap();
+
+    Migrator::new(MIGRATIONS.iter().cloned())?
+        .up(&mut unit)
+        .await?;
+
+    let modified = Migrator::new([
+        MIGRATIONS[0].clone(),
+        Migration::new(2, "add_user_created_at", "SELECT 1", None),
+    ])?;
+    let res = modified.verify(&mut unit).await;
+    assert!(matches!(
+        res,
+        Err(RepositoryError::Migration(
+            MigrationError::ChecksumMismatch { version: 2, .. }
+        ))
+    ));
+
+    let missing = Migrator::new([MIGRATIONS[0].clone()])?;
+    let res = missing.up(&mut unit).await;
+    assert!(matches!(
+        res,
+        Err(RepositoryError::Migration(MigrationError::UnknownApplied {
+            version: 2,
+            ..
+        }))
+    ));
+
+    let status = missing.status(&mut unit).await?;
+    assert_eq!(
+        states(&status),
+        [MigrationState::Applied, MigrationState::Unknown]
+    );
+
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() {
+    with_test_database("", |db| async move { up_and_down(db).await.unwrap() }).await;
+
+    with_test_database(
+        "",
+        |db| async move { concurrent_runners(db).await.unwrap() },
+    )
+    .await;
+
+    with_test_database(
+        "",
+        |db| async move { tampered_migrations(db).await.unwrap() },
+    )
+    .await;
+}
@@ -0,0 +1 @@
+ALTER TABLE public.user DROP COLUMN created_at;
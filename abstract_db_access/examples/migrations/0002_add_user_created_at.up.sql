@@ -0,0 +1 @@
+ALTER TABLE public.user ADD COLUMN created_at TIMESTAMPTZ NOT NULL DEFAULT now();
@@ -0,0 +1,165 @@
+use abstract_db_access::{
+    mock::{MockConnection, MockDb, MockUnit},
+    DbAccess, DbUnit, RepositoryError, SavePoint, TransactionUnit,
+};
+use async_trait::async_trait;
+
+#[derive(Debug, Clone, PartialEq)]
+struct User {
+    id: uuid::Uuid,
+    name: String,
+    email: String,
+}
+
+const USER_TABLE: &str = "public.user";
+
+#[async_trait]
+trait UserRepository: DbAccess {
+    async fn insert(&mut self, user: User) -> Result<(), RepositoryError>;
+    async fn find(&self, id: uuid::Uuid) -> Result<Option<User>, RepositoryError>;
+}
+
+#[async_trait]
+impl<T: MockConnection + DbAccess + Send + Sync> UserRepository for T {
+    async fn insert(&mut self, user: User) -> Result<(), RepositoryError> {
+        self.write(|tables| tables.rows_mut(USER_TABLE).push(user));
+        Ok(())
+    }
+
+    async fn find(&self, id: uuid::Uuid) -> Result<Option<User>, RepositoryError> {
+        let user = self.read(|tables| {
+            tables
+                .rows::<User>(USER_TABLE)
+                .iter()
+                .find(|user| user.id == id)
+                .cloned()
+        });
+        Ok(user)
+    }
+}
+
+async fn multi_repo(mut unit: MockUnit, user: User) -> Result<(), RepositoryError> {
+    UserRepository::insert(&mut unit, user.clone())
+        .await
+        .unwrap();
+
+    assert_eq!(unit.find(user.id).await.unwrap(), Some(user));
+
+    Ok(())
+}
+
+async fn multi_repo_transaction(mut unit: MockUnit, user: User) -> Result<(), RepositoryError> {
+    let db = unit.db().clone();
+    let mut trx = unit.transaction().await.unwrap();
+
+    UserRepository::insert(&mut trx, user.clone())
+        .await
+        .unwrap();
+
+    // the database only sees the user once committed
+    assert_eq!(trx.find(user.id).await.unwrap(), Some(user.clone()));
+    assert!(!db.snapshot().rows::<User>(USER_TABLE).contains(&user));
+
+    trx.commit().await.unwrap();
+
+    assert_eq!(unit.find(user.id).await.unwrap(), Some(user));
+
+    Ok(())
+}
+
+async fn rolled_back_transaction(mut unit: MockUnit, user: User) -> Result<(), RepositoryError> {
+    let mut trx = unit.transaction().await.unwrap();
+
+    UserRepository::insert(&mut trx, user.clone())
+        .await
+        .unwrap();
+
+    trx.rollback().await.unwrap();
+
+    assert_eq!(unit.find(user.id).await.unwrap(), None);
+
+    Ok(())
+}
+
+async fn save_points(mut unit: MockUnit, users: [User; 2]) -> Result<(), RepositoryError> {
+    let [kept, discarded] = users;
+    let mut trx = unit.transaction().await.unwrap();
+
+    let mut save_point = trx.save_point("kept").await.unwrap();
+    assert_eq!(save_point.depth(), 1);
+    UserRepository::insert(&mut save_point, kept.clone())
+        .await
+        .unwrap();
+    save_point.commit().await.unwrap();
+
+    let mut save_point = trx.save_point("discarded").await.unwrap();
+    UserRepository::insert(&mut save_point, discarded.clone())
+        .await
+        .unwrap();
+    save_point.rollback().await.unwrap();
+
+    trx.commit().await.unwrap();
+
+    assert_eq!(unit.find(kept.id).await.unwrap(), Some(kept));
+    assert_eq!(unit.find(discarded.id).await.unwrap(), None);
+
+    Ok(())
+}
+
+async fn concurrent_transactions(db: &MockDb, users: [User; 2]) -> Result<(), RepositoryError> {
+    let [first_user, second_user] = users;
+    let (mut first, mut second) = (db.unit(), db.unit());
+
+    let mut first_trx = first.transaction().await.unwrap();
+    let mut second_trx = second.transaction().await.unwrap();
+
+    UserRepository::insert(&mut first_trx, first_user.clone())
+        .await
+        .unwrap();
+    UserRepository::insert(&mut second_trx, second_user.clone())
+        .await
+        .unwrap();
+
+    // the second commit would overwrite the user table committed by the first one
+    first_trx.commit().await.unwrap();
+    assert!(matches!(
+        second_trx.commit().await,
+        Err(RepositoryError::SerializationFailure(_))
+    ));
+
+    assert_eq!(first.find(first_user.id).await.unwrap(), Some(first_user));
+    assert_eq!(second.find(second_user.id).await.unwrap(), None);
+
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() {
+    let mut users = (0..).map(|idx| User {
+        id: uuid::Uuid::new_v4(),
+        email: format!("rustac{idx}@email.com"),
+        name: format!("Rustacean {idx}"),
+    });
+
+    let db = MockDb::new();
+
+    multi_repo(db.unit(), users.next().unwrap()).await.unwrap();
+
+    multi_repo_transaction(db.unit(), users.next().unwrap())
+        .await
+        .unwrap();
+
+    rolled_back_transaction(db.unit(), users.next().unwrap())
+        .await
+        .unwrap();
+
+    save_points(db.unit(), [users.next().unwrap(), users.next().unwrap()])
+        .await
+        .unwrap();
+
+    concurrent_transactions(&db, [users.next().unwrap(), users.next().unwrap()])
+        .await
+        .unwrap();
+
+    assert_eq!(db.snapshot().rows::<User>(USER_TABLE).len(), 4);
+}
@@ -0,0 +1,74 @@
+use std::{cell::RefCell, rc::Rc};
+
+use abstract_db_access::{
+    mock::{MockConnection, MockDb, MockUnit},
+    native::{DbUnit, SavePoint, TransactionUnit},
+    RepositoryError,
+};
+
+#[derive(Debug, Clone, PartialEq)]
+struct User {
+    id: uuid::Uuid,
+    name: String,
+}
+
+const USER_TABLE: &str = "public.user";
+
+/// Log shared by the tasks of a thread, not `Send`
+type AuditLog = Rc<RefCell<Vec<String>>>;
+
+fn insert(conn: &mut impl MockConnection, user: User) {
+    conn.write(|tables| tables.rows_mut(USER_TABLE).push(user));
+}
+
+/// Holds the log across the awaits, possible since the futures are not required to be `Send`
+async fn register(
+    mut unit: MockUnit,
+    users: [User; 2],
+    log: AuditLog,
+) -> Result<(), RepositoryError> {
+    let [kept, discarded] = users;
+    let mut trx = unit.transaction().await?;
+
+    let mut save_point = trx.save_point("kept").await?;
+    assert_eq!(save_point.depth(), 1);
+    insert(&mut save_point, kept.clone());
+    save_point.commit().await?;
+    log.borrow_mut().push(format!("registered {}", kept.name));
+
+    let mut save_point = trx.save_point("discarded").await?;
+    insert(&mut save_point, discarded.clone());
+    save_point.rollback().await?;
+    log.borrow_mut()
+        .push(format!("discarded {}", discarded.name));
+
+    trx.commit().await
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() {
+    let mut users = (0..).map(|idx| User {
+        id: uuid::Uuid::new_v4(),
+        name: format!("Rustacean {idx}"),
+    });
+
+    let db = MockDb::new();
+    let log = AuditLog::default();
+
+    let local = tokio::task::LocalSet::new();
+    let task = local.spawn_local(register(
+        db.unit(),
+        [users.next().unwrap(), users.next().unwrap()],
+        log.clone(),
+    ));
+    local.await;
+    task.await.unwrap().unwrap();
+
+    let rows = db.snapshot().rows::<User>(USER_TABLE).to_vec();
+    assert_eq!(rows.len(), 1);
+    assert_eq!(rows[0].name, "Rustacean 0");
+    assert_eq!(
+        *log.borrow(),
+        ["registered Rustacean 0", "discarded Rustacean 1"]
+    );
+}
@@ -0,0 +1,129 @@
+// NOTE: `RepositoryError` holds the postgres `DbError` unboxed
+#![allow(clippy::result_large_err)]
+
+use abstract_db_access::{
+    blocking::{DbUnit, SavePoint, TransactionUnit},
+    pg_blocking::PgConnection,
+    DbAccess, RepositoryError,
+};
+use postgres::{GenericClient, NoTls};
+use r2d2_postgres::PostgresConnectionManager;
+use utilities::env_var;
+
+type PgPool = r2d2::Pool<PostgresConnectionManager<NoTls>>;
+
+#[derive(Debug, Clone, PartialEq)]
+struct User {
+    id: uuid::Uuid,
+    name: String,
+    email: String,
+}
+
+trait UserRepository: DbAccess {
+    fn insert(&mut self, user: &User) -> Result<(), RepositoryError>;
+    fn find(&mut self, id: uuid::Uuid) -> Result<Option<User>, RepositoryError>;
+}
+
+impl<T: PgConnection + DbAccess> UserRepository for T {
+    fn insert(&mut self, user: &User) -> Result<(), RepositoryError> {
+        self.pg_client().execute(
+            "INSERT INTO public.user (id, name, email) VALUES ($1, $2, $3)",
+            &[&user.id, &user.name, &user.email],
+        )?;
+        Ok(())
+    }
+
+    fn find(&mut self, id: uuid::Uuid) -> Result<Option<User>, RepositoryError> {
+        let row = self.pg_client().query_opt(
+            "SELECT id, name, email FROM public.user WHERE id = $1",
+            &[&id],
+        )?;
+
+        Ok(row.map(|row| User {
+            id: row.get("id"),
+            name: row.get("name"),
+            email: row.get("email"),
+        }))
+    }
+}
+
+fn multi_repo_transaction(pool: &PgPool, user: User) -> Result<(), RepositoryError> {
+    let mut unit = pool.get().unwrap();
+    let mut trx = DbUnit::transaction(&mut unit).unwrap();
+
+    UserRepository::insert(&mut trx, &user).unwrap();
+
+    trx.commit().unwrap();
+
+    assert_eq!(unit.find(user.id).unwrap(), Some(user));
+
+    Ok(())
+}
+
+fn save_points(pool: &PgPool, users: [User; 2]) -> Result<(), RepositoryError> {
+    let [kept, discarded] = users;
+    let mut unit = pool.get().unwrap();
+    let mut trx = DbUnit::transaction(&mut unit).unwrap();
+
+    let mut save_point = trx.save_point("kept").unwrap();
+    assert_eq!(save_point.depth(), 1);
+    UserRepository::insert(&mut save_point, &kept).unwrap();
+    save_point.commit().unwrap();
+
+    let mut save_point = trx.save_point("discarded").unwrap();
+    UserRepository::insert(&mut save_point, &discarded).unwrap();
+    save_point.rollback().unwrap();
+
+    trx.commit().unwrap();
+
+    assert_eq!(unit.find(kept.id).unwrap(), Some(kept));
+    assert_eq!(unit.find(discarded.id).unwrap(), None);
+
+    Ok(())
+}
+
+fn generic_function<U>(mut unit: U, user: User) -> Result<(), RepositoryError>
+where
+    U: DbUnit + UserRepository,
+    for<'t> U::Transaction<'t>: UserRepository,
+{
+    let mut trx = unit.transaction()?;
+    UserRepository::insert(&mut trx, &user)?;
+    trx.commit()?;
+
+    assert_eq!(unit.find(user.id)?, Some(user));
+
+    Ok(())
+}
+
+fn setup_db(pool: &PgPool) {
+    let mut client = pool.get().unwrap();
+    client
+        .batch_execute(concat!(
+            "DROP SCHEMA IF EXISTS public CASCADE;\n",
+            "CREATE SCHEMA IF NOT EXISTS public;\n",
+            "SET search_path TO public;\n",
+            include_str!("dbschema.sql")
+        ))
+        .unwrap();
+}
+
+fn main() {
+    let mut users = (0..).map(|idx| User {
+        id: uuid::Uuid::new_v4(),
+        email: format!("rustac{idx}@email.com"),
+        name: format!("Rustacean {idx}"),
+    });
+
+    let config = env_var::get().database_url.parse().unwrap();
+    let manager = PostgresConnectionManager::new(config, NoTls);
+    let pool = r2d2::Pool::builder().max_size(2).build(manager).unwrap();
+
+    setup_db(&pool);
+
+    multi_repo_transaction(&pool, users.next().unwrap()).unwrap();
+
+    save_points(&pool, [users.next().unwrap(), users.next().unwrap()]).unwrap();
+
+    generic_function(pool.get().unwrap(), users.next().unwrap()).unwrap();
+}
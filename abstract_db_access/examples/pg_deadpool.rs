@@ -1,60 +1,121 @@
 use abstract_db_access::{
-    pg_deadpool::{PgTrxUnit, PgUnit},
-    DbAccess, DbUnit, RepositoryError, TransactionUnit,
+    pg::{
+        AdvisoryKey, BulkCopy, CopyRow, FromRow, PgConnection, PgEntity, Pipelined, RowLock,
+        SessionAdvisoryLock, TransactionAdvisoryLock, TransactionCursor, TransactionNotify,
+        TransactionRowLock,
+    },
+    pg_deadpool::{
+        tenant::{SchemaName, TenantUnit},
+        PgUnit,
+    },
+    repository, transaction_bound, DbAccess, DbUnit, Entity, QueryStream, Repository,
+    RepositoryError, TransactionUnit,
 };
 use async_trait::async_trait;
+use futures_util::TryStreamExt;
+use std::time::{Duration, Instant};
+use tokio_postgres::types::{ToSql, Type};
 use utilities::connection;
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, FromRow)]
 struct User {
     id: uuid::Uuid,
     name: String,
     email: String,
 }
 
-impl From<tokio_postgres::Row> for User {
-    fn from(row: tokio_postgres::Row) -> Self {
-        Self {
-            id: row.get("id"),
-            name: row.get("name"),
-            email: row.get("email"),
-        }
+#[derive(Debug, FromRow)]
+struct UserSummary {
+    #[row(flatten)]
+    user: User,
+    #[row(rename = "email_domain")]
+    domain: Option<String>,
+    #[row(default)]
+    logins: i64,
+}
+
+impl CopyRow for User {
+    const COLUMNS: &'static [&'static str] = &["id", "name", "email"];
+    const TYPES: &'static [Type] = &[Type::UUID, Type::TEXT, Type::TEXT];
+
+    fn values(&self) -> Vec<&(dyn ToSql + Sync)> {
+        vec![&self.id, &self.name, &self.email]
     }
 }
 
-#[async_trait]
-trait UserRepository: DbAccess {
-    async fn insert(&mut self, user: User) -> Result<(), RepositoryError>;
-    async fn find(&self, id: uuid::Uuid) -> Result<Option<User>, RepositoryError>;
+impl Entity for User {
+    type Key = uuid::Uuid;
+
+    const TABLE: &'static str = "public.user";
+    const KEY: &'static [&'static str] = &["id"];
+    const COLUMNS: &'static [&'static str] = &["id", "name", "email"];
 }
 
-#[async_trait]
-impl UserRepository for PgUnit {
-    async fn insert(&mut self, user: User) -> Result<(), RepositoryError> {
-        self.query(
-            "INSERT INTO public.user (id, name, email) VALUES ($1, $2, $3)",
-            &[&user.id, &user.name, &user.email],
-        )
-        .await?;
-        Ok(())
+impl PgEntity for User {
+    fn key_values(key: &uuid::Uuid) -> Vec<&(dyn ToSql + Sync)> {
+        vec![key]
     }
 
-    async fn find(&self, id: uuid::Uuid) -> Result<Option<User>, RepositoryError> {
-        let row = self
-            .query_opt(
-                "SELECT (id, name, email) FROM public.user WHERE user.id = $1",
-                &[&id],
-            )
-            .await?;
+    fn values(&self) -> Vec<&(dyn ToSql + Sync)> {
+        vec![&self.id, &self.name, &self.email]
+    }
+}
 
-        Ok(row.map(User::from))
+#[derive(Debug, Clone, PartialEq, FromRow)]
+struct Account {
+    id: uuid::Uuid,
+    balance: i64,
+    version: i64,
+}
+
+impl Entity for Account {
+    type Key = uuid::Uuid;
+
+    const TABLE: &'static str = "public.account";
+    const KEY: &'static [&'static str] = &["id"];
+    const COLUMNS: &'static [&'static str] = &["id", "balance", "version"];
+    const VERSION: Option<&'static str> = Some("version");
+
+    fn version(&self) -> Option<i64> {
+        Some(self.version)
     }
+
+    fn set_version(&mut self, version: i64) {
+        self.version = version;
+    }
+}
+
+impl PgEntity for Account {
+    fn key_values(key: &uuid::Uuid) -> Vec<&(dyn ToSql + Sync)> {
+        vec![key]
+    }
+
+    fn values(&self) -> Vec<&(dyn ToSql + Sync)> {
+        vec![&self.id, &self.balance, &self.version]
+    }
+}
+
+#[repository(for = [pg_deadpool])]
+#[async_trait]
+impl<U> Repository<User> for U {}
+
+#[repository(for = [pg_deadpool])]
+#[async_trait]
+impl<U> Repository<Account> for U {}
+
+const USER_INSERT_LOCK: AdvisoryKey = AdvisoryKey::from_name("user:insert");
+
+#[async_trait]
+trait UserRepository: DbAccess {
+    async fn insert(&mut self, user: User) -> Result<(), RepositoryError>;
+    async fn find(&self, id: uuid::Uuid) -> Result<Option<User>, RepositoryError>;
 }
 
+#[repository(for = [pg_deadpool])]
 #[async_trait]
-impl<'t> UserRepository for PgTrxUnit<'t> {
+impl<U: PgConnection + Send + Sync> UserRepository for U {
     async fn insert(&mut self, user: User) -> Result<(), RepositoryError> {
-        self.client
+        self.pg_client()
             .query(
                 "INSERT INTO public.user (id, name, email) VALUES ($1, $2, $3)",
                 &[&user.id, &user.name, &user.email],
@@ -65,44 +126,360 @@ impl<'t> UserRepository for PgTrxUnit<'t> {
 
     async fn find(&self, id: uuid::Uuid) -> Result<Option<User>, RepositoryError> {
         let row = self
-            .client
+            .pg_client()
             .query_opt(
-                "SELECT (id, name, email) FROM public.user WHERE user.id = $1",
+                "SELECT id, name, email FROM public.user WHERE id = $1",
                 &[&id],
             )
             .await?;
 
-        Ok(row.map(User::from))
+        row.as_ref().map(User::from_row).transpose()
     }
 }
 
 async fn multi_repo_transaction(mut unit: PgUnit, user: User) -> Result<(), RepositoryError> {
     let mut trx = DbUnit::transaction(&mut unit).await.unwrap();
 
+    trx.advisory_lock(USER_INSERT_LOCK).await.unwrap();
+
     UserRepository::insert(&mut trx, user.clone())
         .await
         .unwrap();
 
+    trx.notify("user_created", &user.id.to_string())
+        .await
+        .unwrap();
+
+    trx.commit().await.unwrap();
+
+    Ok(())
+}
+
+async fn deadline_transaction(mut unit: PgUnit, user: User) -> Result<(), RepositoryError> {
+    let deadline = Instant::now() + Duration::from_secs(5);
+    let mut trx = unit.transaction_with_deadline(deadline).await.unwrap();
+
+    let deadline = trx.deadline();
+    deadline
+        .run(UserRepository::insert(&mut trx, user.clone()))
+        .await
+        .unwrap();
+
     trx.commit().await.unwrap();
 
     Ok(())
 }
 
+async fn expired_statement(mut unit: PgUnit) -> Result<(), RepositoryError> {
+    let deadline = Instant::now() + Duration::from_millis(200);
+    let trx = unit.transaction_with_deadline(deadline).await.unwrap();
+
+    // not wrapped by the deadline, the statement is cancelled by the server when it passes
+    let res = trx.pg_client().execute("SELECT pg_sleep(5)", &[]).await;
+    assert!(matches!(
+        res.map_err(RepositoryError::from),
+        Err(RepositoryError::Timeout)
+    ));
+    assert!(matches!(trx.commit().await, Err(RepositoryError::Timeout)));
+
+    Ok(())
+}
+
+async fn single_connection_deadline() -> Result<(), RepositoryError> {
+    let pool = connection::create_pg_deadpool_sized(1);
+
+    let mut unit = pool.get().await?;
+    let started = Instant::now();
+    let trx = unit
+        .transaction_with_deadline(started + Duration::from_millis(200))
+        .await
+        .unwrap();
+
+    // the pool has no connection left to cancel the statement through, it falls back to a
+    // cancel request
+    let deadline = trx.deadline();
+    let res = deadline
+        .run(trx.pg_client().execute("SELECT pg_sleep(5)", &[]))
+        .await;
+    assert!(matches!(res, Err(RepositoryError::Timeout)));
+    assert!(started.elapsed() < Duration::from_secs(2));
+    drop(trx);
+    drop(unit);
+
+    // the statement was cancelled, the connection is free again
+    let unit = pool.get().await?;
+    unit.execute("SELECT 1", &[]).await?;
+    assert!(started.elapsed() < Duration::from_secs(2));
+
+    Ok(())
+}
+
+async fn dropped_tenant_unit() -> Result<(), RepositoryError> {
+    let pool = connection::create_pg_deadpool_sized(1);
+
+    let unit = pool.get().await?;
+    unit.batch_execute("CREATE SCHEMA IF NOT EXISTS tenant_example")
+        .await?;
+    drop(unit);
+
+    let schema = SchemaName::new("tenant_example")?;
+    let unit = TenantUnit::acquire(&pool, schema).await?;
+    let pid: i32 = unit.query_one("SELECT pg_backend_pid()", &[]).await?.get(0);
+    let search_path: String = unit.query_one("SHOW search_path", &[]).await?.get(0);
+    assert_eq!(search_path, "\"tenant_example\"");
+    drop(unit);
+
+    // the dropped unit reset the search_path and went back to the pool
+    let unit = pool.get().await?;
+    let row = unit
+        .query_one(
+            "SELECT pg_backend_pid(), current_setting('search_path')",
+            &[],
+        )
+        .await?;
+    assert_eq!(row.get::<_, i32>(0), pid);
+    assert_ne!(row.get::<_, String>(1), "\"tenant_example\"");
+
+    Ok(())
+}
+
+async fn bulk_insert(mut unit: PgUnit, users: Vec<User>) -> Result<(), RepositoryError> {
+    let trx = DbUnit::transaction(&mut unit).await.unwrap();
+
+    let rows = futures_util::stream::iter(users.into_iter().map(Ok::<_, RepositoryError>));
+    trx.copy_rows("public.user", rows).await.unwrap();
+
+    trx.commit().await.unwrap();
+
+    Ok(())
+}
+
+async fn export_users(mut unit: PgUnit, ids: &[uuid::Uuid]) -> Result<usize, RepositoryError> {
+    let query = "SELECT id, name, email FROM public.user WHERE id = ANY($1)";
+
+    let streamed = unit
+        .query_stream((query, &[&ids]))
+        .try_fold(0, |count, _| async move { Ok(count + 1) })
+        .await?;
+
+    let trx = DbUnit::transaction(&mut unit).await?;
+    let exported = trx
+        .cursor_stream("user_export", (query, &[&ids]), 25)
+        .and_then(|row| async move { User::from_row(&row) })
+        .try_fold(0, |count, _| async move { Ok(count + 1) })
+        .await?;
+    trx.commit().await?;
+
+    assert_eq!(streamed, exported);
+    Ok(exported)
+}
+
+async fn crud_repository(mut unit: PgUnit, mut users: Vec<User>) -> Result<(), RepositoryError> {
+    let mut trx = DbUnit::transaction(&mut unit).await?;
+    Repository::insert(&mut trx, &users[0]).await?;
+    assert_eq!(Repository::insert_many(&mut trx, &users[1..]).await?, 2);
+    trx.commit().await?;
+
+    for user in &users {
+        assert!(Repository::<User>::exists(&unit, &user.id).await?);
+    }
+
+    users[0].name = "Ferris".to_owned();
+    assert!(Repository::update(&mut unit, &mut users[0]).await?);
+    let found = Repository::<User>::find_by_id(&unit, &users[0].id).await?;
+    assert_eq!(found.as_ref(), Some(&users[0]));
+
+    let keys: Vec<_> = users.iter().map(|user| user.id).collect();
+    let mut found = Repository::<User>::find_many(&unit, &keys).await?;
+    found.sort_by_key(|user| user.email.clone());
+    assert_eq!(found, users);
+
+    // more keys than the parameters limit of a statement
+    let mut keys: Vec<_> = (0..70_000).map(|_| uuid::Uuid::new_v4()).collect();
+    keys.push(users[0].id);
+    let found = Repository::<User>::find_many(&unit, &keys).await?;
+    assert_eq!(found, users[..1]);
+
+    assert!(Repository::<User>::delete(&mut unit, &users[2].id).await?);
+    assert!(!Repository::<User>::exists(&unit, &users[2].id).await?);
+    assert!(!Repository::update(&mut unit, &mut users[2]).await?);
+
+    Ok(())
+}
+
+async fn optimistic_update(mut unit: PgUnit) -> Result<(), RepositoryError> {
+    let id = uuid::Uuid::new_v4();
+    let account = Account {
+        id,
+        balance: 100,
+        version: 0,
+    };
+    Repository::insert(&mut unit, &account).await?;
+
+    // both read the same version, the second update would lose the first one
+    let mut first = Repository::<Account>::find_by_id(&unit, &id)
+        .await?
+        .unwrap();
+    let mut second = first.clone();
+
+    first.balance += 10;
+    assert!(Repository::update(&mut unit, &mut first).await?);
+    assert_eq!(first.version, 1);
+
+    second.balance -= 30;
+    let res = Repository::update(&mut unit, &mut second).await;
+    assert!(matches!(
+        res,
+        Err(RepositoryError::ConcurrencyConflict {
+            entity: "public.account",
+            expected: 0,
+            ..
+        })
+    ));
+
+    // retried with the current version
+    let mut second = Repository::<Account>::find_by_id(&unit, &id)
+        .await?
+        .unwrap();
+    assert_eq!(second.version, 1);
+    second.balance -= 30;
+    assert!(Repository::update(&mut unit, &mut second).await?);
+
+    // the bumped version is kept, so the entity can be updated again
+    second.balance += 10;
+    assert!(Repository::update(&mut unit, &mut second).await?);
+
+    let account = Repository::<Account>::find_by_id(&unit, &id)
+        .await?
+        .unwrap();
+    assert_eq!((account.balance, account.version), (90, 3));
+
+    Ok(())
+}
+
+async fn row_locking(pool: &deadpool_postgres::Pool) -> Result<(), RepositoryError> {
+    let mut first = pool.get().await?;
+    let mut second = pool.get().await?;
+
+    let id = uuid::Uuid::new_v4();
+    let account = Account {
+        id,
+        balance: 100,
+        version: 0,
+    };
+    Repository::insert(&mut first, &account).await?;
+
+    let mut first_trx = DbUnit::transaction(&mut first).await?;
+    let mut locked: Account = first_trx
+        .find_by_id_for_update(&id, RowLock::Wait)
+        .await?
+        .unwrap();
+
+    let second_trx = DbUnit::transaction(&mut second).await?;
+    let res: Result<Option<Account>, _> =
+        second_trx.find_by_id_for_update(&id, RowLock::NoWait).await;
+    assert!(matches!(res, Err(RepositoryError::LockNotAvailable)));
+    second_trx.rollback().await?;
+
+    let second_trx = DbUnit::transaction(&mut second).await?;
+    let skipped: Vec<Account> = second_trx
+        .find_many_for_update(&[id], RowLock::SkipLocked)
+        .await?;
+    assert!(skipped.is_empty());
+    second_trx.rollback().await?;
+
+    locked.balance -= 30;
+    Repository::update(&mut first_trx, &mut locked).await?;
+    first_trx.commit().await?;
+
+    let account = Repository::<Account>::find_by_id(&second, &id)
+        .await?
+        .unwrap();
+    assert_eq!(account.balance, 70);
+
+    Ok(())
+}
+
+async fn map_rows(mut unit: PgUnit, user: User) -> Result<(), RepositoryError> {
+    UserRepository::insert(&mut unit, user.clone()).await?;
+
+    let row = unit
+        .query_one(
+            "SELECT id, name, email, NULLIF(split_part(email, '@', 2), 'email.com') AS email_domain
+            FROM public.user WHERE id = $1",
+            &[&user.id],
+        )
+        .await?;
+    let summary = UserSummary::from_row(&row)?;
+    assert_eq!(summary.user, user);
+    assert_eq!(summary.domain, None);
+    assert_eq!(summary.logins, 0);
+
+    // the type mismatch is returned instead of panicking
+    let row = unit
+        .query_one(
+            "SELECT id, 42 AS name, email FROM public.user WHERE id = $1",
+            &[&user.id],
+        )
+        .await?;
+    assert!(matches!(
+        User::from_row(&row),
+        Err(RepositoryError::Unknown(_))
+    ));
+
+    Ok(())
+}
+
+async fn pipelined_insert(mut unit: PgUnit, user: User) -> Result<u64, RepositoryError> {
+    let insert = "INSERT INTO public.user (id, name, email) VALUES ($1, $2, $3)";
+    let count = "SELECT count(*) FROM public.user WHERE id = $1";
+    let params: [&(dyn ToSql + Sync); 3] = [&user.id, &user.name, &user.email];
+
+    let outputs = unit
+        .pipeline()
+        .execute(insert, &params)
+        .query(count, &[&user.id])
+        .run()
+        .await?;
+    assert_eq!(outputs[0].affected(), Some(1));
+
+    // the duplicated insert fails, rolling back the whole pipeline
+    let failed = unit
+        .pipeline()
+        .query(count, &[&user.id])
+        .execute(insert, &params)
+        .run()
+        .await;
+    assert!(failed.is_err());
+
+    // the connection is left outside of the transaction of the pipeline
+    let probe = unit.batch_execute("SAVEPOINT probe").await.unwrap_err();
+    assert_eq!(
+        probe.code(),
+        Some(&tokio_postgres::error::SqlState::NO_ACTIVE_SQL_TRANSACTION)
+    );
+
+    let count: i64 = unit.query_one(count, &[&user.id]).await?.get(0);
+    Ok(count as u64)
+}
+
 async fn multi_repo(mut unit: PgUnit, user: User) -> Result<(), RepositoryError> {
-    UserRepository::insert(&mut unit, user.clone())
+    let mut lock = unit.advisory_lock(USER_INSERT_LOCK).await.unwrap();
+
+    UserRepository::insert(&mut *lock, user.clone())
         .await
         .unwrap();
 
+    lock.unlock().await.unwrap();
+
     Ok(())
 }
 
-#[allow(dead_code)]
-async fn generic_function<Unit, Trx>(mut unit: Unit, user: User) -> Result<(), RepositoryError>
+transaction_bound!(trait UserTransaction: UserRepository);
+
+async fn generic_function<Unit>(mut unit: Unit, user: User) -> Result<(), RepositoryError>
 where
-    for<'t> Unit: DbUnit<Transaction<'t> = Trx>,
-    Unit: UserRepository,
-    Trx: TransactionUnit,
-    Trx: UserRepository,
+    Unit: DbUnit + UserRepository + for<'t> UserTransaction<'t>,
 {
     let mut trx = unit.transaction().await.unwrap();
 
@@ -112,7 +489,7 @@ where
 
     trx.commit().await.unwrap();
 
-    let restored_user = UserRepository::find(&mut unit, user.id).await.unwrap();
+    let restored_user = UserRepository::find(&unit, user.id).await.unwrap();
 
     assert_eq!(restored_user, Some(user));
 
@@ -156,7 +533,47 @@ async fn main() {
         .await
         .unwrap();
 
-    // NOTE: HRTB issue
-    // let client = pool.get().await.unwrap();
-    // generic_function(client, user.clone()).await.unwrap();
+    let client = pool.get().await.unwrap();
+    deadline_transaction(client, users.next().unwrap().clone())
+        .await
+        .unwrap();
+
+    let client = pool.get().await.unwrap();
+    expired_statement(client).await.unwrap();
+
+    single_connection_deadline().await.unwrap();
+
+    dropped_tenant_unit().await.unwrap();
+
+    let bulk_users: Vec<User> = users.by_ref().take(100).collect();
+    let ids: Vec<_> = bulk_users.iter().map(|user| user.id).collect();
+    let client = pool.get().await.unwrap();
+    bulk_insert(client, bulk_users).await.unwrap();
+
+    let client = pool.get().await.unwrap();
+    assert_eq!(export_users(client, &ids).await.unwrap(), 100);
+
+    let client = pool.get().await.unwrap();
+    let count = pipelined_insert(client, users.next().unwrap().clone())
+        .await
+        .unwrap();
+    assert_eq!(count, 1);
+
+    let client = pool.get().await.unwrap();
+    map_rows(client, users.next().unwrap()).await.unwrap();
+
+    let client = pool.get().await.unwrap();
+    crud_repository(client, users.by_ref().take(3).collect())
+        .await
+        .unwrap();
+
+    let client = pool.get().await.unwrap();
+    optimistic_update(client).await.unwrap();
+
+    row_locking(&pool).await.unwrap();
+
+    let client = pool.get().await.unwrap();
+    generic_function(client, users.next().unwrap())
+        .await
+        .unwrap();
 }
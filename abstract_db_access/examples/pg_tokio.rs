@@ -0,0 +1,177 @@
+use abstract_db_access::{
+    pg::{FromRow, PgConnection},
+    pg_tokio::{lazy::LazyUnit, PgUnit},
+    repository, transaction_bound, DbAccess, DbUnit, RepositoryError, SavePoint,
+    TransactionOptions, TransactionUnit,
+};
+use async_trait::async_trait;
+use std::time::Instant;
+use utilities::connection;
+
+#[derive(Debug, Clone, PartialEq, FromRow)]
+struct User {
+    id: uuid::Uuid,
+    name: String,
+    email: String,
+}
+
+#[async_trait]
+trait UserRepository: DbAccess {
+    async fn insert(&mut self, user: User) -> Result<(), RepositoryError>;
+    async fn find(&self, id: uuid::Uuid) -> Result<Option<User>, RepositoryError>;
+    async fn update_name(&mut self, id: uuid::Uuid, name: &str) -> Result<(), RepositoryError>;
+}
+
+#[repository(for = [pg_tokio, pg_tokio_lazy])]
+#[async_trait]
+impl<U: PgConnection + Send + Sync> UserRepository for U {
+    async fn insert(&mut self, user: User) -> Result<(), RepositoryError> {
+        self.pg_client()
+            .execute(
+                "INSERT INTO public.user (id, name, email) VALUES ($1, $2, $3)",
+                &[&user.id, &user.name, &user.email],
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn find(&self, id: uuid::Uuid) -> Result<Option<User>, RepositoryError> {
+        let row = self
+            .pg_client()
+            .query_opt(
+                "SELECT id, name, email FROM public.user WHERE id = $1",
+                &[&id],
+            )
+            .await?;
+
+        row.as_ref().map(User::from_row).transpose()
+    }
+
+    async fn update_name(&mut self, id: uuid::Uuid, name: &str) -> Result<(), RepositoryError> {
+        self.pg_client()
+            .execute(
+                "UPDATE public.user SET name = $2 WHERE id = $1",
+                &[&id, &name],
+            )
+            .await?;
+        Ok(())
+    }
+}
+
+async fn rolled_back_transaction(unit: &mut PgUnit, user: User) -> Result<(), RepositoryError> {
+    let mut trx = unit.transaction().await.unwrap();
+
+    UserRepository::insert(&mut trx, user.clone())
+        .await
+        .unwrap();
+
+    trx.rollback().await.unwrap();
+
+    assert_eq!(unit.find(user.id).await.unwrap(), None);
+
+    Ok(())
+}
+
+async fn lazy_transaction(unit: PgUnit, user: User) -> Result<PgUnit, RepositoryError> {
+    let mut unit = LazyUnit::new(unit);
+
+    // nothing is sent to the server by a transaction without statements
+    let trx = unit.transaction().await.unwrap();
+    assert!(!trx.is_begun());
+    trx.commit().await.unwrap();
+
+    // the repository begins the transaction through its client
+    let mut trx = unit.transaction().await.unwrap();
+    UserRepository::insert(&mut trx, user.clone())
+        .await
+        .unwrap();
+    assert!(trx.is_begun());
+    trx.commit().await.unwrap();
+
+    assert_eq!(unit.find(user.id).await.unwrap(), Some(user.clone()));
+
+    // a save point begins the transaction, its rollback keeps the statements run before it
+    let mut trx = unit.transaction().await.unwrap();
+    UserRepository::update_name(&mut trx, user.id, "Renamed")
+        .await
+        .unwrap();
+    let mut point = trx.save_point("lazy").await.unwrap();
+    UserRepository::update_name(&mut point, user.id, "Discarded")
+        .await
+        .unwrap();
+    point.rollback().await.unwrap();
+    trx.commit().await.unwrap();
+
+    let renamed = unit.find(user.id).await.unwrap().unwrap();
+    assert_eq!(renamed.name, "Renamed");
+
+    // the deadline is checked by the commit even if the transaction was not begun
+    let options = TransactionOptions::new().with_deadline(Instant::now());
+    let trx = unit.transaction_with(options).await.unwrap();
+    assert!(matches!(trx.commit().await, Err(RepositoryError::Timeout)));
+
+    // the commit returns the error `BEGIN` failed with
+    let options = TransactionOptions::new().set_local("statement_timeout", "soon");
+    let trx = unit.transaction_with(options).await.unwrap();
+    assert!(trx.pg_client().query("SELECT 1", &[]).await.is_err());
+    let err = format!("{:?}", trx.commit().await.unwrap_err());
+    assert!(err.contains("statement_timeout"), "{err}");
+
+    Ok(unit.into_inner())
+}
+
+transaction_bound!(trait UserTransaction: UserRepository);
+
+async fn generic_function<Unit>(mut unit: Unit, user: User) -> Result<(), RepositoryError>
+where
+    Unit: DbUnit + UserRepository + for<'t> UserTransaction<'t>,
+{
+    let mut trx = unit.transaction().await.unwrap();
+
+    UserRepository::insert(&mut trx, user.clone())
+        .await
+        .unwrap();
+
+    trx.commit().await.unwrap();
+
+    let restored_user = UserRepository::find(&unit, user.id).await.unwrap();
+
+    assert_eq!(restored_user, Some(user));
+
+    Ok(())
+}
+
+async fn setup_db(unit: &PgUnit) {
+    unit.client()
+        .batch_execute(concat!(
+            "DROP SCHEMA IF EXISTS public CASCADE;\n",
+            "CREATE SCHEMA IF NOT EXISTS public;\n",
+            "SET search_path TO public;\n",
+            include_str!("dbschema.sql")
+        ))
+        .await
+        .unwrap();
+}
+
+#[tokio::main]
+async fn main() {
+    let mut users = (0..).map(|idx| User {
+        id: uuid::Uuid::new_v4(),
+        email: format!("rustac{idx}@email.com"),
+        name: format!("Rustacean {idx}"),
+    });
+
+    let mut unit = PgUnit::new(connection::create_pg_client().await);
+
+    setup_db(&unit).await;
+
+    rolled_back_transaction(&mut unit, users.next().unwrap())
+        .await
+        .unwrap();
+
+    let unit = lazy_transaction(unit, users.next().unwrap()).await.unwrap();
+
+    generic_function(LazyUnit::new(unit), users.next().unwrap())
+        .await
+        .unwrap();
+}
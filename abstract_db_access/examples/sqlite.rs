@@ -0,0 +1,138 @@
+// NOTE: `RepositoryError` holds the postgres `DbError` unboxed
+#![allow(clippy::result_large_err)]
+
+use std::time::{Duration, Instant};
+
+use abstract_db_access::{
+    blocking::{DbUnit, SavePoint, TransactionUnit},
+    sqlite::{SqliteConnection, SqliteUnit},
+    DbAccess, RepositoryError,
+};
+use rusqlite::OptionalExtension;
+
+#[derive(Debug, Clone, PartialEq)]
+struct User {
+    id: i64,
+    name: String,
+    email: String,
+}
+
+trait UserRepository: DbAccess {
+    fn insert(&mut self, user: &User) -> Result<(), RepositoryError>;
+    fn find(&self, id: i64) -> Result<Option<User>, RepositoryError>;
+}
+
+impl<T: SqliteConnection + DbAccess> UserRepository for T {
+    fn insert(&mut self, user: &User) -> Result<(), RepositoryError> {
+        self.connection().execute(
+            "INSERT INTO user (id, name, email) VALUES (?1, ?2, ?3)",
+            (&user.id, &user.name, &user.email),
+        )?;
+        Ok(())
+    }
+
+    fn find(&self, id: i64) -> Result<Option<User>, RepositoryError> {
+        let user = self
+            .connection()
+            .query_row(
+                "SELECT id, name, email FROM user WHERE id = ?1",
+                [id],
+                |row| {
+                    Ok(User {
+                        id: row.get(0)?,
+                        name: row.get(1)?,
+                        email: row.get(2)?,
+                    })
+                },
+            )
+            .optional()?;
+        Ok(user)
+    }
+}
+
+fn multi_repo_transaction(unit: &mut SqliteUnit, user: User) -> Result<(), RepositoryError> {
+    let mut trx = DbUnit::transaction(unit).unwrap();
+
+    UserRepository::insert(&mut trx, &user).unwrap();
+    assert_eq!(trx.find(user.id).unwrap(), Some(user.clone()));
+
+    trx.commit().unwrap();
+
+    assert_eq!(unit.find(user.id).unwrap(), Some(user));
+
+    Ok(())
+}
+
+fn rolled_back_transaction(unit: &mut SqliteUnit, user: User) -> Result<(), RepositoryError> {
+    let mut trx = DbUnit::transaction(unit).unwrap();
+
+    UserRepository::insert(&mut trx, &user).unwrap();
+
+    trx.rollback().unwrap();
+
+    assert_eq!(unit.find(user.id).unwrap(), None);
+
+    Ok(())
+}
+
+fn save_points(unit: &mut SqliteUnit, users: [User; 2]) -> Result<(), RepositoryError> {
+    let [kept, discarded] = users;
+    let mut trx = DbUnit::transaction(unit).unwrap();
+
+    let mut save_point = trx.save_point("kept").unwrap();
+    assert_eq!(save_point.depth(), 1);
+    UserRepository::insert(&mut save_point, &kept).unwrap();
+    save_point.commit().unwrap();
+
+    let mut save_point = trx.save_point("discarded").unwrap();
+    UserRepository::insert(&mut save_point, &discarded).unwrap();
+    save_point.rollback().unwrap();
+
+    // the rolled back save point is released too
+    assert!(trx.connection().execute("RELEASE discarded", []).is_err());
+
+    trx.commit().unwrap();
+
+    assert_eq!(unit.find(kept.id).unwrap(), Some(kept));
+    assert_eq!(unit.find(discarded.id).unwrap(), None);
+
+    Ok(())
+}
+
+fn expired_transaction(unit: &mut SqliteUnit, user: User) -> Result<(), RepositoryError> {
+    let deadline = Instant::now() + Duration::from_millis(10);
+    let mut trx = unit.transaction_with_deadline(deadline).unwrap();
+
+    UserRepository::insert(&mut trx, &user).unwrap();
+    std::thread::sleep(Duration::from_millis(20));
+
+    assert!(matches!(trx.commit(), Err(RepositoryError::Timeout)));
+    assert_eq!(unit.find(user.id).unwrap(), None);
+
+    Ok(())
+}
+
+fn main() {
+    let mut users = (0..).map(|idx| User {
+        id: idx,
+        email: format!("rustac{idx}@email.com"),
+        name: format!("Rustacean {idx}"),
+    });
+
+    let mut unit = SqliteUnit::open_in_memory().unwrap();
+    unit.execute_batch("CREATE TABLE user (id INTEGER PRIMARY KEY, name TEXT, email TEXT)")
+        .unwrap();
+
+    multi_repo_transaction(&mut unit, users.next().unwrap()).unwrap();
+
+    rolled_back_transaction(&mut unit, users.next().unwrap()).unwrap();
+
+    save_points(&mut unit, [users.next().unwrap(), users.next().unwrap()]).unwrap();
+
+    expired_transaction(&mut unit, users.next().unwrap()).unwrap();
+
+    let count: i64 = unit
+        .query_row("SELECT count(*) FROM user", [], |row| row.get(0))
+        .unwrap();
+    assert_eq!(count, 2);
+}
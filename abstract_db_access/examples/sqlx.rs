@@ -1,9 +1,11 @@
 use abstract_db_access::{
-    sqlx::{SqlxTrxUnit, SqlxUnit},
-    DbAccess, DbUnit, RepositoryError, TransactionUnit,
+    repository, sqlx::SqlxUnit, transaction_bound, DbAccess, DbUnit, QueryStream, RepositoryError,
+    TransactionUnit,
 };
 use async_trait::async_trait;
-use sqlx::Executor;
+use futures_util::TryStreamExt;
+use sqlx::{Executor, FromRow};
+use std::time::{Duration, Instant};
 use utilities::connection;
 
 #[derive(Debug, Clone, PartialEq, sqlx::FromRow)]
@@ -38,7 +40,7 @@ where
     E: sqlx::Executor<'e, Database = sqlx::Postgres>,
 {
     if let Some(user) =
-        sqlx::query_as::<_, User>("SELECT (id, name, email) FROM public.user WHERE user.id = $1")
+        sqlx::query_as::<_, User>("SELECT id, name, email FROM public.user WHERE id = $1")
             .bind(id)
             .fetch_optional(executor)
             .await
@@ -50,8 +52,9 @@ where
     Ok(None)
 }
 
+#[repository(for = [sqlx(sqlx::Postgres)])]
 #[async_trait]
-impl UserRepository for SqlxUnit<sqlx::Postgres> {
+impl<E> UserRepository for E {
     async fn insert(&mut self, user: User) -> Result<(), RepositoryError> {
         insert_user(self, user).await
     }
@@ -61,29 +64,18 @@ impl UserRepository for SqlxUnit<sqlx::Postgres> {
     }
 }
 
-#[async_trait]
-impl<'t> UserRepository for SqlxTrxUnit<'t, sqlx::Postgres> {
-    async fn insert(&mut self, user: User) -> Result<(), RepositoryError> {
-        insert_user(self, user).await
-    }
-
-    async fn find(&mut self, id: uuid::Uuid) -> Result<Option<User>, RepositoryError> {
-        find_user(self, id).await
-    }
-}
+async fn multi_repo_transaction(
+    mut unit: SqlxUnit<sqlx::Postgres>,
+    user: User,
+) -> Result<(), RepositoryError> {
+    let mut trx = DbUnit::transaction(&mut unit).await.unwrap();
 
-// async fn multi_repo_transaction(
-//     mut unit: SqlxUnit<sqlx::Postgres>,
-//     user: User,
-// ) -> Result<(), RepositoryError> {
-//     let mut trx = DbUnit::transaction(&mut unit).await.unwrap();
+    UserRepository::insert(&mut trx, user).await.unwrap();
 
-//     UserRepository::insert(&mut trx, user).await.unwrap();
-
-//     trx.commit().await.unwrap();
+    trx.commit().await.unwrap();
 
-//     Ok(())
-// }
+    Ok(())
+}
 
 async fn multi_repo(mut unit: SqlxUnit<sqlx::Postgres>, user: User) -> Result<(), RepositoryError> {
     UserRepository::insert(&mut unit, user).await.unwrap();
@@ -91,13 +83,22 @@ async fn multi_repo(mut unit: SqlxUnit<sqlx::Postgres>, user: User) -> Result<()
     Ok(())
 }
 
-#[allow(dead_code)]
-async fn generic_function<Unit, Trx>(mut unit: Unit, user: User) -> Result<(), RepositoryError>
+async fn count_users(unit: &mut SqlxUnit<sqlx::Postgres>) -> Result<usize, RepositoryError> {
+    let query = sqlx::query("SELECT id, name, email FROM public.user");
+    let users: Vec<User> = unit
+        .query_stream(query)
+        .and_then(|row| async move { User::from_row(&row).map_err(RepositoryError::from) })
+        .try_collect()
+        .await?;
+
+    Ok(users.len())
+}
+
+transaction_bound!(trait UserTransaction: UserRepository);
+
+async fn generic_function<Unit>(mut unit: Unit, user: User) -> Result<(), RepositoryError>
 where
-    for<'t> Unit: DbUnit<Transaction<'t> = Trx>,
-    Unit: UserRepository,
-    Trx: TransactionUnit,
-    Trx: UserRepository,
+    Unit: DbUnit + UserRepository + for<'t> UserTransaction<'t>,
 {
     let mut trx = unit.transaction().await.unwrap();
 
@@ -114,6 +115,23 @@ where
     Ok(())
 }
 
+async fn expired_transaction(
+    mut unit: SqlxUnit<sqlx::Postgres>,
+    user: User,
+) -> Result<(), RepositoryError> {
+    let deadline = Instant::now() + Duration::from_millis(100);
+    let mut trx = unit.transaction_with_deadline(deadline).await?;
+    UserRepository::insert(&mut trx, user.clone()).await?;
+
+    tokio::time::sleep_until(deadline.into()).await;
+    assert!(matches!(trx.commit().await, Err(RepositoryError::Timeout)));
+
+    // rolled back instead of committed
+    assert_eq!(UserRepository::find(&mut unit, user.id).await?, None);
+
+    Ok(())
+}
+
 async fn setup_db(pool: &sqlx::PgPool) {
     let mut client = pool.acquire().await.unwrap();
 
@@ -146,12 +164,21 @@ async fn main() {
         .await
         .unwrap();
 
-    // let client = pool.acquire().await.unwrap();
-    // multi_repo_transaction(client, users.next().unwrap().clone())
-    //     .await
-    //     .unwrap();
+    let mut client = pool.acquire().await.unwrap();
+    assert_eq!(count_users(&mut client).await.unwrap(), 1);
 
-    // NOTE: HRTB issue
-    // let client = pool.acquire().await.unwrap();
-    // generic_function(client, user.clone()).await.unwrap();
+    let client = pool.acquire().await.unwrap();
+    multi_repo_transaction(client, users.next().unwrap().clone())
+        .await
+        .unwrap();
+
+    let client = pool.acquire().await.unwrap();
+    generic_function(client, users.next().unwrap())
+        .await
+        .unwrap();
+
+    let client = pool.acquire().await.unwrap();
+    expired_transaction(client, users.next().unwrap())
+        .await
+        .unwrap();
 }
@@ -0,0 +1,80 @@
+use abstract_db_access::{pg_deadpool::PgTrxUnit, DbUnit, RepositoryError, TransactionUnit};
+use utilities::{
+    connection,
+    test_database::{with_test_database, TestDatabase},
+};
+
+const SCHEMA: &str = include_str!("dbschema.sql");
+const COUNT_USERS: &str = "SELECT count(*) FROM public.user";
+
+async fn insert_user(trx: &PgTrxUnit<'_>, idx: usize) -> Result<(), RepositoryError> {
+    trx.client
+        .execute(
+            "INSERT INTO public.user (id, name, email) VALUES ($1, $2, $3)",
+            &[
+                &uuid::Uuid::new_v4(),
+                &format!("Rustacean {idx}"),
+                &format!("rustac{idx}@email.com"),
+            ],
+        )
+        .await?;
+    Ok(())
+}
+
+/// Test committing users, only seeing its own commits
+async fn commit_users_test(users: usize) -> String {
+    with_test_database(SCHEMA, |db: TestDatabase| async move {
+        let pool = db.create_pg_deadpool();
+        let mut unit = pool.get().await.unwrap();
+
+        let trx = unit.transaction().await.unwrap();
+        for idx in 0..users {
+            insert_user(&trx, idx).await.unwrap();
+        }
+        trx.commit().await.unwrap();
+
+        let count: i64 = unit.query_one(COUNT_USERS, &[]).await.unwrap().get(0);
+        assert_eq!(count, users as i64);
+
+        db.name().to_owned()
+    })
+    .await
+}
+
+/// Test panicking after committing a user
+async fn panicking_test() {
+    with_test_database(SCHEMA, |db: TestDatabase| async move {
+        let pool = db.create_sqlx_pool().await;
+        sqlx::query(
+            "INSERT INTO public.user (id, name, email) VALUES ($1, 'Ferris', 'ferris@email.com')",
+        )
+        .bind(uuid::Uuid::new_v4())
+        .execute(&pool)
+        .await
+        .unwrap();
+        panic!("test failed");
+    })
+    .await
+}
+
+#[tokio::main]
+async fn main() {
+    // the tests run concurrently, each on its own database
+    let (first, second) = tokio::join!(commit_users_test(1), commit_users_test(5));
+    assert_ne!(first, second);
+
+    let panicked = tokio::spawn(panicking_test()).await;
+    assert!(panicked.unwrap_err().is_panic());
+
+    let pool = connection::create_pg_deadpool();
+    let unit = pool.get().await.unwrap();
+    let leftovers: i64 = unit
+        .query_one(
+            "SELECT count(*) FROM pg_database WHERE datname IN ($1, $2)",
+            &[&first, &second],
+        )
+        .await
+        .unwrap()
+        .get(0);
+    assert_eq!(leftovers, 0);
+}
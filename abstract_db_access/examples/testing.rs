@@ -0,0 +1,110 @@
+use abstract_db_access::{
+    pg_deadpool::PgTrxUnit,
+    testing::{with_deadpool_transaction, with_test_transaction},
+    DbUnit, RepositoryError, SavePoint, TransactionUnit,
+};
+use futures_util::FutureExt;
+use utilities::connection;
+
+const COUNT_USERS: &str = "SELECT count(*) FROM public.user";
+
+async fn insert_user(trx: &PgTrxUnit<'_>, idx: usize) -> Result<(), RepositoryError> {
+    trx.client
+        .execute(
+            "INSERT INTO public.user (id, name, email) VALUES ($1, $2, $3)",
+            &[
+                &uuid::Uuid::new_v4(),
+                &format!("Rustacean {idx}"),
+                &format!("rustac{idx}@email.com"),
+            ],
+        )
+        .await?;
+    Ok(())
+}
+
+async fn count_users(trx: &PgTrxUnit<'_>) -> Result<i64, RepositoryError> {
+    Ok(trx.client.query_one(COUNT_USERS, &[]).await?.get(0))
+}
+
+/// Test inserting users, only seeing its own insertions
+async fn insert_users_test(pool: &deadpool_postgres::Pool, users: usize) {
+    with_deadpool_transaction(pool, |trx| {
+        async move {
+            let before = count_users(trx).await.unwrap();
+            for idx in 0..users {
+                insert_user(trx, idx).await.unwrap();
+            }
+            assert_eq!(count_users(trx).await.unwrap(), before + users as i64);
+        }
+        .boxed()
+    })
+    .await
+    .unwrap();
+}
+
+/// Test committing through a save point, still rolled back with the test transaction
+async fn save_point_test(pool: &deadpool_postgres::Pool) {
+    let mut unit = pool.get().await.unwrap();
+
+    with_test_transaction(&mut unit, |trx| {
+        async move {
+            let save_point = trx.save_point("committed").await.unwrap();
+            insert_user(&save_point, 0).await.unwrap();
+            save_point.commit().await.unwrap();
+
+            assert_eq!(count_users(trx).await.unwrap(), 1);
+        }
+        .boxed()
+    })
+    .await
+    .unwrap();
+}
+
+/// Test panicking after inserting a user
+async fn panicking_test(pool: deadpool_postgres::Pool) {
+    with_deadpool_transaction(&pool, |trx| {
+        async move {
+            insert_user(trx, 0).await.unwrap();
+            panic!("test failed");
+        }
+        .boxed()
+    })
+    .await
+    .unwrap();
+}
+
+async fn setup_db(pool: &deadpool_postgres::Pool) {
+    let mut client = pool.get().await.unwrap();
+    let trx = client.transaction().await.unwrap();
+    trx.client
+        .batch_execute(concat!(
+            "DROP SCHEMA IF EXISTS public CASCADE;\n",
+            "CREATE SCHEMA IF NOT EXISTS public;\n",
+            "SET search_path TO public;\n",
+            include_str!("dbschema.sql")
+        ))
+        .await
+        .unwrap();
+    trx.commit().await.unwrap();
+}
+
+#[tokio::main]
+async fn main() {
+    let pool = connection::create_pg_deadpool();
+
+    setup_db(&pool).await;
+
+    // the tests run concurrently, each on its own connection
+    tokio::join!(
+        insert_users_test(&pool, 1),
+        insert_users_test(&pool, 5),
+        save_point_test(&pool),
+    );
+
+    let panicked = tokio::spawn(panicking_test(pool.clone())).await;
+    assert!(panicked.unwrap_err().is_panic());
+
+    let unit = pool.get().await.unwrap();
+    let count: i64 = unit.query_one(COUNT_USERS, &[]).await.unwrap().get(0);
+    assert_eq!(count, 0);
+}
@@ -0,0 +1,142 @@
+//! Unit of work traits for synchronous database clients.
+//!
+//! Mirrors [`DbUnit`](crate::DbUnit), [`TransactionUnit`](crate::TransactionUnit) and
+//! [`SavePoint`](crate::SavePoint) with blocking methods, keeping the same guarantees: a
+//! transaction is only opened from a unit, is consumed by commit or rollback, and save points
+//! are only created inside a transaction.
+
+use std::time::Instant;
+
+use super::{unsupported_options, DbAccess, RepositoryError, TransactionOptions};
+
+pub trait Transactor {
+    type Transaction<'t>: TransactionUnit;
+}
+
+pub trait DbUnit: DbAccess + Transactor {
+    /// Creates a new transaction.
+    fn transaction(&mut self) -> Result<Self::Transaction<'_>, RepositoryError>;
+
+    /// Creates a new transaction configured by the `options`.
+    ///
+    /// The default implementation only accepts the default options.
+    fn transaction_with(
+        &mut self,
+        options: TransactionOptions,
+    ) -> Result<Self::Transaction<'_>, RepositoryError> {
+        if options != TransactionOptions::default() {
+            return Err(unsupported_options());
+        }
+        self.transaction()
+    }
+
+    /// Creates a new transaction that must be finished before the `deadline`.
+    ///
+    /// Committing the transaction after the deadline rolls it back and returns
+    /// [`RepositoryError::Timeout`].
+    fn transaction_with_deadline(
+        &mut self,
+        deadline: Instant,
+    ) -> Result<Self::Transaction<'_>, RepositoryError> {
+        self.transaction_with(TransactionOptions::new().with_deadline(deadline))
+    }
+}
+
+/// Transaction of a unit, consumed by commit or rollback.
+///
+/// Dropping the transaction without commit rolls it back.
+pub trait TransactionUnit: DbAccess + Transactor + Sized {
+    fn commit(self) -> Result<(), RepositoryError>;
+    fn rollback(self) -> Result<(), RepositoryError>;
+}
+
+pub trait SavePoint: TransactionUnit {
+    fn save_point(&mut self, name: &str) -> Result<Self::Transaction<'_>, RepositoryError>;
+
+    /// Returns the nested level
+    fn depth(&self) -> u32;
+}
+
+/// Marker of the types whose statements run inside a transaction, see
+/// [`InTransaction`](crate::InTransaction).
+pub trait InTransaction: TransactionUnit {}
+
+#[cfg(feature = "r2d2_pool")]
+mod pool {
+    use r2d2::{ManageConnection, PooledConnection};
+
+    use super::{DbUnit, Transactor};
+    use crate::{AutoCommit, DbAccess, RepositoryError, TransactionOptions};
+
+    impl<M> DbAccess for PooledConnection<M>
+    where
+        M: ManageConnection,
+        M::Connection: DbAccess,
+    {
+        type Connection = <M::Connection as DbAccess>::Connection;
+    }
+
+    impl<M> Transactor for PooledConnection<M>
+    where
+        M: ManageConnection,
+        M::Connection: Transactor,
+    {
+        type Transaction<'t> = <M::Connection as Transactor>::Transaction<'t>;
+    }
+
+    impl<M> AutoCommit for PooledConnection<M>
+    where
+        M: ManageConnection,
+        M::Connection: AutoCommit,
+    {
+    }
+
+    impl<M> DbUnit for PooledConnection<M>
+    where
+        M: ManageConnection,
+        M::Connection: DbUnit,
+    {
+        fn transaction(&mut self) -> Result<Self::Transaction<'_>, RepositoryError> {
+            DbUnit::transaction(&mut **self)
+        }
+
+        fn transaction_with(
+            &mut self,
+            options: TransactionOptions,
+        ) -> Result<Self::Transaction<'_>, RepositoryError> {
+            DbUnit::transaction_with(&mut **self, options)
+        }
+    }
+
+    #[cfg(feature = "pg_blocking")]
+    impl<M> crate::pg_blocking::PgConnection for PooledConnection<M>
+    where
+        M: ManageConnection,
+        M::Connection: crate::pg_blocking::PgConnection,
+    {
+        type Client = <M::Connection as crate::pg_blocking::PgConnection>::Client;
+
+        fn pg_client(&mut self) -> &mut Self::Client {
+            (**self).pg_client()
+        }
+
+        fn is_transaction(&self) -> bool {
+            (**self).is_transaction()
+        }
+    }
+
+    #[cfg(feature = "sqlite")]
+    impl<M> crate::sqlite::SqliteConnection for PooledConnection<M>
+    where
+        M: ManageConnection,
+        M::Connection: crate::sqlite::SqliteConnection,
+    {
+        fn connection(&self) -> &rusqlite::Connection {
+            (**self).connection()
+        }
+
+        fn is_transaction(&self) -> bool {
+            (**self).is_transaction()
+        }
+    }
+}
@@ -0,0 +1,529 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    future::Future,
+    ops::{Deref, DerefMut},
+    pin::Pin,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use async_trait::async_trait;
+
+#[cfg(feature = "native_async")]
+use super::native;
+use super::{
+    AutoCommit, DbAccess, DbUnit, InTransaction, RepositoryError, SavePoint, TransactionOptions,
+    TransactionUnit, Transactor,
+};
+
+const DEFAULT_SEED: u64 = 0x9e37_79b9_7f4a_7c15;
+
+/// Operation of the unit of work where a fault can be injected
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FaultPoint {
+    /// Opening a transaction, through any of the `DbUnit` methods
+    Begin,
+    Commit,
+    Rollback,
+    SavePoint,
+    /// Running a statement through the `statement` method of the faulty unit or transaction
+    Statement,
+}
+
+/// Failure injected into an operation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fault {
+    /// Fails with [`RepositoryError::SerializationFailure`], the transaction is rolled back
+    SerializationFailure,
+    /// Fails with [`RepositoryError::ConnectionClosed`], the transaction is rolled back and its
+    /// following operations fail the same way
+    ConnectionLost,
+    /// Delays the operation, which then runs normally
+    Latency(Duration),
+}
+
+impl Fault {
+    fn error(&self) -> Option<RepositoryError> {
+        match self {
+            Fault::SerializationFailure => Some(RepositoryError::SerializationFailure(None)),
+            Fault::ConnectionLost => Some(RepositoryError::ConnectionClosed),
+            Fault::Latency(_) => None,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct InjectorInner {
+    scripts: HashMap<FaultPoint, VecDeque<Option<Fault>>>,
+    probabilities: HashMap<FaultPoint, Vec<(Fault, f64)>>,
+    rng: u64,
+    injected: Vec<(FaultPoint, Fault)>,
+}
+
+impl InjectorInner {
+    /// Next value of a xorshift64* generator, mapped to `[0, 1)`
+    fn next_f64(&mut self) -> f64 {
+        self.rng ^= self.rng >> 12;
+        self.rng ^= self.rng << 25;
+        self.rng ^= self.rng >> 27;
+        let value = self.rng.wrapping_mul(0x2545_f491_4f6c_dd1d);
+        (value >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    fn next_fault(&mut self, point: FaultPoint) -> Option<Fault> {
+        if let Some(scripted) = self.scripts.get_mut(&point).and_then(VecDeque::pop_front) {
+            return scripted;
+        }
+
+        let candidates = self.probabilities.get(&point)?.clone();
+        candidates
+            .into_iter()
+            .find(|(_, probability)| self.next_f64() < *probability)
+            .map(|(fault, _)| fault)
+    }
+}
+
+/// Decides the faults injected by the faulty units.
+///
+/// Scripted faults are injected first, in order, one per occurrence of their point, a `None`
+/// entry letting the occurrence run normally. Once the script of a point is exhausted the
+/// faults are drawn from its probability table, using a seeded generator so runs are
+/// reproducible.
+///
+/// Cloning the injector gives access to the same script and history, so it can be inspected
+/// after handing it to a unit.
+#[derive(Debug, Clone)]
+pub struct FaultInjector {
+    inner: Arc<Mutex<InjectorInner>>,
+}
+
+impl FaultInjector {
+    pub fn new() -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(InjectorInner {
+                scripts: HashMap::new(),
+                probabilities: HashMap::new(),
+                rng: DEFAULT_SEED,
+                injected: Vec::new(),
+            })),
+        }
+    }
+
+    /// Appends the `faults` to the script of the `point`.
+    pub fn script(
+        self,
+        point: FaultPoint,
+        faults: impl IntoIterator<Item = Option<Fault>>,
+    ) -> Self {
+        self.inner
+            .lock()
+            .unwrap()
+            .scripts
+            .entry(point)
+            .or_default()
+            .extend(faults);
+        self
+    }
+
+    /// Injects the `fault` on the `point` with the `probability`, between 0 and 1.
+    ///
+    /// The faults of a point are drawn in the order they were added, at most one per
+    /// occurrence.
+    pub fn probability(self, point: FaultPoint, fault: Fault, probability: f64) -> Self {
+        self.inner
+            .lock()
+            .unwrap()
+            .probabilities
+            .entry(point)
+            .or_default()
+            .push((fault, probability));
+        self
+    }
+
+    /// Seeds the generator of the probability tables.
+    pub fn seed(self, seed: u64) -> Self {
+        // NOTE: xorshift gets stuck on a zero state
+        self.inner.lock().unwrap().rng = if seed == 0 { DEFAULT_SEED } else { seed };
+        self
+    }
+
+    /// Faults injected so far, in order
+    pub fn injected(&self) -> Vec<(FaultPoint, Fault)> {
+        self.inner.lock().unwrap().injected.clone()
+    }
+
+    /// Wraps the `unit` to inject the faults into it.
+    pub fn wrap<U: DbUnit>(&self, unit: U) -> FaultyUnit<U> {
+        FaultyUnit {
+            inner: unit,
+            injector: self.clone(),
+        }
+    }
+
+    /// Draws the fault of the `point`, if any, recording it as injected.
+    fn draw(&self, point: FaultPoint) -> Option<Fault> {
+        let mut inner = self.inner.lock().unwrap();
+        let fault = inner.next_fault(point);
+        if let Some(fault) = fault {
+            inner.injected.push((point, fault));
+        }
+        fault
+    }
+
+    /// Injects the fault of the `point`, if any.
+    ///
+    /// Latencies are waited here, the other faults are returned as their error.
+    async fn inject(&self, point: FaultPoint) -> Result<(), RepositoryError> {
+        match self.draw(point) {
+            Some(Fault::Latency(latency)) => {
+                tokio::time::sleep(latency).await;
+                Ok(())
+            }
+            Some(fault) => Err(fault.error().unwrap()),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Default for FaultInjector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Unit with faults injected into its unit of work operations.
+///
+/// The statements run through the wrapped unit, accessible by deref, are left untouched. Those
+/// run on the unit returned by [`FaultyUnit::statement`] get the faults of
+/// [`FaultPoint::Statement`].
+#[derive(Debug)]
+pub struct FaultyUnit<U> {
+    inner: U,
+    injector: FaultInjector,
+}
+
+impl<U> FaultyUnit<U> {
+    pub fn injector(&self) -> &FaultInjector {
+        &self.injector
+    }
+
+    /// Injects the fault of [`FaultPoint::Statement`], returning the wrapped unit to run the
+    /// statement on.
+    pub async fn statement(&mut self) -> Result<&mut U, RepositoryError> {
+        self.injector.inject(FaultPoint::Statement).await?;
+        Ok(&mut self.inner)
+    }
+
+    pub fn into_inner(self) -> U {
+        self.inner
+    }
+}
+
+impl<U> Deref for FaultyUnit<U> {
+    type Target = U;
+
+    fn deref(&self) -> &Self::Target {
+        &self.inner
+    }
+}
+
+impl<U> DerefMut for FaultyUnit<U> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.inner
+    }
+}
+
+impl<U: DbAccess> DbAccess for FaultyUnit<U> {
+    type Connection = U::Connection;
+}
+
+impl<U> Transactor for FaultyUnit<U>
+where
+    U: Transactor,
+{
+    type Transaction<'t> = FaultyTrxUnit<U::Transaction<'t>>;
+}
+
+impl<U: AutoCommit> AutoCommit for FaultyUnit<U> {}
+
+#[async_trait]
+impl<U> DbUnit for FaultyUnit<U>
+where
+    U: DbUnit + Send,
+{
+    async fn transaction<'s>(&'s mut self) -> Result<Self::Transaction<'s>, RepositoryError> {
+        self.injector.inject(FaultPoint::Begin).await?;
+        let inner = self.inner.transaction().await?;
+        Ok(FaultyTrxUnit::new(inner, self.injector.clone()))
+    }
+
+    async fn transaction_with<'s>(
+        &'s mut self,
+        options: TransactionOptions,
+    ) -> Result<Self::Transaction<'s>, RepositoryError> {
+        self.injector.inject(FaultPoint::Begin).await?;
+        let inner = self.inner.transaction_with(options).await?;
+        Ok(FaultyTrxUnit::new(inner, self.injector.clone()))
+    }
+}
+
+#[cfg(feature = "native_async")]
+impl<U> native::Transactor for FaultyUnit<U>
+where
+    U: native::Transactor,
+{
+    type Transaction<'t> = FaultyTrxUnit<U::Transaction<'t>>;
+}
+
+#[cfg(feature = "native_async")]
+impl<U> native::DbUnit for FaultyUnit<U>
+where
+    U: native::DbUnit,
+{
+    async fn transaction<'s>(
+        &'s mut self,
+    ) -> Result<FaultyTrxUnit<<U as native::Transactor>::Transaction<'s>>, RepositoryError> {
+        self.injector.inject(FaultPoint::Begin).await?;
+        let inner = native::DbUnit::transaction(&mut self.inner).await?;
+        Ok(FaultyTrxUnit::new(inner, self.injector.clone()))
+    }
+
+    async fn transaction_with<'s>(
+        &'s mut self,
+        options: TransactionOptions,
+    ) -> Result<FaultyTrxUnit<<U as native::Transactor>::Transaction<'s>>, RepositoryError> {
+        self.injector.inject(FaultPoint::Begin).await?;
+        let inner = native::DbUnit::transaction_with(&mut self.inner, options).await?;
+        Ok(FaultyTrxUnit::new(inner, self.injector.clone()))
+    }
+}
+
+/// Transaction with faults injected into its unit of work operations.
+///
+/// Once its connection is lost the transaction is only rolled back, by commit or rollback, and
+/// every operation fails with [`RepositoryError::ConnectionClosed`].
+#[derive(Debug)]
+pub struct FaultyTrxUnit<T> {
+    inner: T,
+    injector: FaultInjector,
+    lost: bool,
+}
+
+impl<T> FaultyTrxUnit<T> {
+    fn new(inner: T, injector: FaultInjector) -> Self {
+        Self {
+            inner,
+            injector,
+            lost: false,
+        }
+    }
+
+    /// Indicates if the connection of the transaction was lost by an injected fault
+    pub fn is_lost(&self) -> bool {
+        self.lost
+    }
+
+    /// Injects the fault of [`FaultPoint::Statement`], returning the wrapped transaction to run
+    /// the statement on.
+    ///
+    /// A [`Fault::ConnectionLost`] drops the connection between two statements: this one and
+    /// every following operation fail with [`RepositoryError::ConnectionClosed`].
+    pub async fn statement(&mut self) -> Result<&mut T, RepositoryError> {
+        if self.lost {
+            return Err(RepositoryError::ConnectionClosed);
+        }
+
+        if let Err(err) = self.injector.inject(FaultPoint::Statement).await {
+            self.lost = matches!(err, RepositoryError::ConnectionClosed);
+            return Err(err);
+        }
+
+        Ok(&mut self.inner)
+    }
+}
+
+impl<T> Deref for FaultyTrxUnit<T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.inner
+    }
+}
+
+impl<T> DerefMut for FaultyTrxUnit<T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.inner
+    }
+}
+
+impl<T: DbAccess> DbAccess for FaultyTrxUnit<T> {
+    type Connection = T::Connection;
+}
+
+impl<T> Transactor for FaultyTrxUnit<T>
+where
+    T: Transactor,
+{
+    type Transaction<'t> = FaultyTrxUnit<T::Transaction<'t>>;
+}
+
+impl<T: InTransaction> InTransaction for FaultyTrxUnit<T> {}
+
+type FinishFuture<'a> = Pin<Box<dyn Future<Output = Result<(), RepositoryError>> + Send + 'a>>;
+
+/// Waits the `latency`, then finishes the wrapped transaction by `fut` and returns the `error`.
+fn finish_with(
+    latency: Option<Duration>,
+    fut: FinishFuture<'_>,
+    error: Option<RepositoryError>,
+) -> FinishFuture<'_> {
+    Box::pin(async move {
+        if let Some(latency) = latency {
+            tokio::time::sleep(latency).await;
+        }
+        fut.await?;
+        error.map_or(Ok(()), Err)
+    })
+}
+
+// NOTE: implemented without `async_trait`, the future finishing the wrapped transaction is
+// created before waiting for the injected latency, so the wrapped transaction is moved into its
+// own `Send` future instead of being required to be `Send`
+impl<T> TransactionUnit for FaultyTrxUnit<T>
+where
+    T: TransactionUnit,
+{
+    fn commit<'a>(self) -> FinishFuture<'a>
+    where
+        Self: 'a,
+    {
+        if self.lost {
+            return finish_with(
+                None,
+                self.inner.rollback(),
+                Some(RepositoryError::ConnectionClosed),
+            );
+        }
+
+        match self.injector.draw(FaultPoint::Commit) {
+            None => self.inner.commit(),
+            Some(Fault::Latency(latency)) => finish_with(Some(latency), self.inner.commit(), None),
+            Some(fault) => finish_with(None, self.inner.rollback(), fault.error()),
+        }
+    }
+
+    fn rollback<'a>(self) -> FinishFuture<'a>
+    where
+        Self: 'a,
+    {
+        if self.lost {
+            return finish_with(
+                None,
+                self.inner.rollback(),
+                Some(RepositoryError::ConnectionClosed),
+            );
+        }
+
+        match self.injector.draw(FaultPoint::Rollback) {
+            None => self.inner.rollback(),
+            Some(Fault::Latency(latency)) => {
+                finish_with(Some(latency), self.inner.rollback(), None)
+            }
+            Some(fault) => finish_with(None, self.inner.rollback(), fault.error()),
+        }
+    }
+}
+
+#[async_trait]
+impl<T> SavePoint for FaultyTrxUnit<T>
+where
+    T: SavePoint + Send,
+{
+    async fn save_point<'s>(
+        &'s mut self,
+        name: &str,
+    ) -> Result<Self::Transaction<'s>, RepositoryError> {
+        if self.lost {
+            return Err(RepositoryError::ConnectionClosed);
+        }
+
+        if let Err(err) = self.injector.inject(FaultPoint::SavePoint).await {
+            self.lost = matches!(err, RepositoryError::ConnectionClosed);
+            return Err(err);
+        }
+
+        let inner = self.inner.save_point(name).await?;
+        Ok(FaultyTrxUnit::new(inner, self.injector.clone()))
+    }
+
+    fn depth(&self) -> u32 {
+        self.inner.depth()
+    }
+}
+
+#[cfg(feature = "native_async")]
+impl<T> native::Transactor for FaultyTrxUnit<T>
+where
+    T: native::Transactor,
+{
+    type Transaction<'t> = FaultyTrxUnit<T::Transaction<'t>>;
+}
+
+#[cfg(feature = "native_async")]
+impl<T> native::TransactionUnit for FaultyTrxUnit<T>
+where
+    T: native::TransactionUnit,
+{
+    async fn commit(self) -> Result<(), RepositoryError> {
+        if self.lost {
+            native::TransactionUnit::rollback(self.inner).await?;
+            return Err(RepositoryError::ConnectionClosed);
+        }
+
+        match self.injector.inject(FaultPoint::Commit).await {
+            Ok(()) => native::TransactionUnit::commit(self.inner).await,
+            Err(err) => {
+                native::TransactionUnit::rollback(self.inner).await?;
+                Err(err)
+            }
+        }
+    }
+
+    async fn rollback(self) -> Result<(), RepositoryError> {
+        if self.lost {
+            native::TransactionUnit::rollback(self.inner).await?;
+            return Err(RepositoryError::ConnectionClosed);
+        }
+
+        let injected = self.injector.inject(FaultPoint::Rollback).await;
+        native::TransactionUnit::rollback(self.inner).await?;
+        injected
+    }
+}
+
+#[cfg(feature = "native_async")]
+impl<T> native::SavePoint for FaultyTrxUnit<T>
+where
+    T: native::SavePoint,
+{
+    async fn save_point<'s>(
+        &'s mut self,
+        name: &str,
+    ) -> Result<FaultyTrxUnit<<T as native::Transactor>::Transaction<'s>>, RepositoryError> {
+        if self.lost {
+            return Err(RepositoryError::ConnectionClosed);
+        }
+
+        if let Err(err) = self.injector.inject(FaultPoint::SavePoint).await {
+            self.lost = matches!(err, RepositoryError::ConnectionClosed);
+            return Err(err);
+        }
+
+        let inner = native::SavePoint::save_point(&mut self.inner, name).await?;
+        Ok(FaultyTrxUnit::new(inner, self.injector.clone()))
+    }
+
+    fn depth(&self) -> u32 {
+        native::SavePoint::depth(&self.inner)
+    }
+}
@@ -1,4 +1,17 @@
+// NOTE: `RepositoryError::TokioPostgres` keeps the `DbError` unboxed, boxing it would break the
+// downstream matches on the variant
+#![allow(clippy::result_large_err)]
+
+use std::{
+    pin::Pin,
+    time::{Duration, Instant},
+};
+
 use async_trait::async_trait;
+use futures_util::Stream;
+
+#[cfg(feature = "macros")]
+pub use abstract_db_access_macros::repository;
 
 pub trait DbAccess {
     type Connection;
@@ -12,8 +25,36 @@ pub trait Transactor {
 pub trait DbUnit: DbAccess + Transactor {
     /// Creates a new transaction.
     async fn transaction<'s>(&'s mut self) -> Result<Self::Transaction<'s>, RepositoryError>;
+
+    /// Creates a new transaction configured by the `options`.
+    ///
+    /// The default implementation only accepts the default options, units supporting a deadline
+    /// or settings override it.
+    async fn transaction_with<'s>(
+        &'s mut self,
+        options: TransactionOptions,
+    ) -> Result<Self::Transaction<'s>, RepositoryError> {
+        if options != TransactionOptions::default() {
+            return Err(unsupported_options());
+        }
+        self.transaction().await
+    }
+
+    /// Creates a new transaction that must be finished before the `deadline`.
+    ///
+    /// When the deadline passes, the running query is cancelled, the transaction is rolled back
+    /// and [`RepositoryError::Timeout`] is returned. A commit still running at the deadline
+    /// may have been applied by the server, see [`RepositoryError::Timeout`].
+    async fn transaction_with_deadline<'s>(
+        &'s mut self,
+        deadline: Instant,
+    ) -> Result<Self::Transaction<'s>, RepositoryError> {
+        self.transaction_with(TransactionOptions::new().with_deadline(deadline))
+            .await
+    }
 }
 
+/// Transaction of a unit, consumed by commit or rollback.
 #[async_trait]
 pub trait TransactionUnit: DbAccess + Transactor {
     async fn commit(self) -> Result<(), RepositoryError>;
@@ -31,6 +72,343 @@ pub trait SavePoint: TransactionUnit + Sized {
     fn depth(&self) -> u32;
 }
 
+/// Marker of the types whose statements run inside a transaction, committed or rolled back
+/// together.
+///
+/// Required by repository methods issuing several statements that must be atomic, so calling
+/// them on a unit does not compile.
+pub trait InTransaction: TransactionUnit {}
+
+/// Marker of the types whose statements are committed one by one, outside of a transaction.
+///
+/// Required by repository methods that must not run inside a transaction, e.g. to be visible
+/// to other connections right away.
+pub trait AutoCommit: DbAccess {}
+
+/// Declares a trait bounding the transactions of a unit, whatever their lifetime.
+///
+/// A function generic over the unit can not be called with a bound like
+/// `for<'t> Unit::Transaction<'t>: UserRepository`, the compiler fails to prove it while the
+/// unit type is still being inferred. The declared trait moves the bound to the unit, as
+/// `Unit: for<'t> UserTransaction<'t>`, and is implemented for every unit whose transactions
+/// satisfy it.
+///
+/// ```ignore
+/// transaction_bound!(trait UserTransaction: UserRepository);
+///
+/// async fn create_user<Unit>(mut unit: Unit, user: User) -> Result<(), RepositoryError>
+/// where
+///     Unit: DbUnit + UserRepository + for<'t> UserTransaction<'t>,
+/// {
+///     let mut trx = unit.transaction().await?;
+///     trx.insert(user).await?;
+///     trx.commit().await
+/// }
+/// ```
+#[macro_export]
+macro_rules! transaction_bound {
+    ($(#[$attr:meta])* $vis:vis trait $name:ident: $($bound:tt)+) => {
+        $(#[$attr])*
+        $vis trait $name<'t>:
+            $crate::Transactor<Transaction<'t> = <Self as $name<'t>>::Trx>
+        {
+            type Trx: $crate::TransactionUnit + $($bound)+;
+        }
+
+        impl<'t, U> $name<'t> for U
+        where
+            U: $crate::Transactor,
+            U::Transaction<'t>: $($bound)+,
+        {
+            type Trx = U::Transaction<'t>;
+        }
+    };
+}
+
+/// Common interface to run SQL on any backend.
+///
+/// Statements are sent without parameters and rows are read in their text format, enough for
+/// backend-neutral tooling like the schema migrations.
+#[async_trait]
+pub trait DbDriver: DbAccess {
+    /// Executes the statements of the `sql`, separated by semicolons.
+    async fn execute_script(&mut self, sql: &str) -> Result<(), RepositoryError>;
+
+    /// Runs the `sql` query, returning the values of the rows in their text format.
+    ///
+    /// Not every backend converts the values, the columns of other types are cast to `text` in
+    /// the `sql`.
+    async fn query_text(&mut self, sql: &str) -> Result<Vec<Vec<Option<String>>>, RepositoryError>;
+}
+
+/// Stream of the rows returned by a query
+pub type RowStream<'s, Row> = Pin<Box<dyn Stream<Item = Result<Row, RepositoryError>> + Send + 's>>;
+
+/// Query results streamed as they are received, instead of collected in memory.
+///
+/// The rows are read from the connection as the stream is polled, so a slow consumer holds the
+/// server back instead of buffering the whole result set.
+pub trait QueryStream: DbAccess {
+    type Row;
+    /// Statement along with its parameters
+    type Query<'q>;
+
+    fn query_stream<'s, 'q: 's>(&'s mut self, query: Self::Query<'q>) -> RowStream<'s, Self::Row>;
+}
+
+/// Metadata of an entity stored in a table, driving the default [`Repository`] statements.
+///
+/// The values of the entity are bound by the Postgres backends, through `pg::PgEntity`.
+///
+/// ```ignore
+/// impl Entity for User {
+///     type Key = Uuid;
+///     const TABLE: &'static str = "public.user";
+///     const KEY: &'static [&'static str] = &["id"];
+///     const COLUMNS: &'static [&'static str] = &["id", "name", "email"];
+/// }
+/// ```
+pub trait Entity: Send + Sync {
+    /// Primary key, a tuple for composite keys
+    type Key: Send + Sync;
+
+    /// Table, possibly schema qualified (e.g. `public.user`)
+    const TABLE: &'static str;
+    /// Columns of the primary key, in the order of the key values
+    const KEY: &'static [&'static str];
+    /// Columns of the table, including the key ones, in the order of the values
+    const COLUMNS: &'static [&'static str];
+    /// `bigint` column of the optimistic locking, checked and incremented by the updates
+    const VERSION: Option<&'static str> = None;
+
+    /// Version the entity was read with, required when [`Entity::VERSION`] is set
+    fn version(&self) -> Option<i64> {
+        None
+    }
+
+    /// Stores the version written by an update, required when [`Entity::VERSION`] is set
+    fn set_version(&mut self, version: i64) {
+        let _ = version;
+    }
+}
+
+/// Values bound to a [`Repository`] statement
+pub enum Bind<'a, E: Entity> {
+    /// Values of the entity, in the order of the columns
+    Columns(&'a E),
+    /// Values of the key, in the order of the key columns
+    Key(&'a E::Key),
+}
+
+/// Statements of the default [`Repository`] methods, implemented by the async Postgres units
+/// and transactions (`pg_tokio` and `pg_deadpool`) for the entities they can bind and read.
+///
+/// The statements are SQL with positional placeholders, so the mock backend, which has no SQL,
+/// doesn't implement it. Neither do the sqlx units, which need exclusive access to run the
+/// reads the [`Repository`] runs on a shared reference.
+#[async_trait]
+pub trait EntityDriver<E: Entity>: DbAccess + Send + Sync {
+    /// Limit of parameters in a statement
+    const MAX_PARAMETERS: usize;
+
+    /// Placeholder of the parameter at `idx`, starting at 1
+    fn placeholder(idx: usize) -> String;
+
+    /// Validates and quotes a, possibly schema qualified, identifier.
+    fn quote_identifier(name: &str) -> Result<String, RepositoryError>;
+
+    /// Values of the key of the `entity`, described in the errors
+    fn describe_key(entity: &E) -> String;
+
+    /// Executes the `sql`, returning the number of rows changed.
+    async fn execute_entity(
+        &mut self,
+        sql: &str,
+        binds: &[Bind<'_, E>],
+    ) -> Result<u64, RepositoryError>;
+
+    /// Runs the `sql` query, reading the entities of the rows.
+    async fn query_entities(
+        &self,
+        sql: &str,
+        binds: &[Bind<'_, E>],
+    ) -> Result<Vec<E>, RepositoryError>;
+
+    /// Runs the `sql` query, reading the `bigint` of the first row, if any.
+    async fn query_i64(
+        &self,
+        sql: &str,
+        binds: &[Bind<'_, E>],
+    ) -> Result<Option<i64>, RepositoryError>;
+}
+
+/// CRUD operations of an entity, implemented by default from the [`Entity`] metadata.
+///
+/// Available on the async Postgres units and transactions, through [`EntityDriver`], an empty
+/// impl is enough for simple tables and any statement can still be overridden:
+///
+/// ```ignore
+/// #[repository(for = [pg_deadpool])]
+/// #[async_trait]
+/// impl<U> Repository<User> for U {}
+/// ```
+#[async_trait]
+pub trait Repository<E: Entity>: EntityDriver<E> {
+    /// Inserts the `entity`.
+    async fn insert(&mut self, entity: &E) -> Result<(), RepositoryError> {
+        let statement = repository::insert_statement::<E, Self>(1)?;
+        self.execute_entity(&statement, &[Bind::Columns(entity)])
+            .await?;
+        Ok(())
+    }
+
+    /// Inserts the `entities`, returning the number of rows written.
+    ///
+    /// The rows are sent in as few statements as the parameters limit allows, on a unit each
+    /// statement is committed on its own.
+    async fn insert_many(&mut self, entities: &[E]) -> Result<u64, RepositoryError> {
+        let mut inserted = 0;
+        for chunk in entities.chunks(repository::chunk_size(Self::MAX_PARAMETERS, E::COLUMNS)) {
+            let statement = repository::insert_statement::<E, Self>(chunk.len())?;
+            let binds: Vec<_> = chunk.iter().map(Bind::Columns).collect();
+            inserted += self.execute_entity(&statement, &binds).await?;
+        }
+
+        Ok(inserted)
+    }
+
+    /// Updates the columns of the `entity` found by its key, returning if it was found.
+    ///
+    /// Versioned entities are only updated if their version is unchanged, the incremented
+    /// version being stored in the `entity` so it can be updated again. Otherwise
+    /// [`RepositoryError::ConcurrencyConflict`] is returned, also when the entity was deleted,
+    /// and the entity should be read again before retrying.
+    async fn update(&mut self, entity: &mut E) -> Result<bool, RepositoryError> {
+        let statement = repository::update_statement::<E, Self>()?;
+        let version = match E::VERSION {
+            Some(version) => version,
+            None => {
+                let updated = self
+                    .execute_entity(&statement, &[Bind::Columns(entity)])
+                    .await?;
+                return Ok(updated > 0);
+            }
+        };
+
+        let expected = entity.version().ok_or_else(|| {
+            RepositoryError::Unknown(
+                format!("{} has no value for its version column {version}", E::TABLE).into(),
+            )
+        })?;
+        let bumped = self.query_i64(&statement, &[Bind::Columns(entity)]).await?;
+        match bumped {
+            Some(bumped) => {
+                entity.set_version(bumped);
+                Ok(true)
+            }
+            None => Err(RepositoryError::ConcurrencyConflict {
+                entity: E::TABLE,
+                id: Self::describe_key(entity),
+                expected,
+            }),
+        }
+    }
+
+    /// Deletes the entity of the `key`, returning if it was found.
+    async fn delete(&mut self, key: &E::Key) -> Result<bool, RepositoryError> {
+        let statement = format!(
+            "DELETE FROM {} WHERE {}",
+            Self::quote_identifier(E::TABLE)?,
+            repository::key_condition::<E, Self>(0)?
+        );
+        let deleted = self.execute_entity(&statement, &[Bind::Key(key)]).await?;
+        Ok(deleted > 0)
+    }
+
+    /// Finds the entity of the `key`.
+    async fn find_by_id(&self, key: &E::Key) -> Result<Option<E>, RepositoryError> {
+        let statement = repository::find_many_statement::<E, Self>(1)?;
+        let found = self.query_entities(&statement, &[Bind::Key(key)]).await?;
+        Ok(found.into_iter().next())
+    }
+
+    /// Finds the entities of the `keys`, missing ones are skipped.
+    ///
+    /// The keys are sent in as few statements as the parameters limit allows.
+    async fn find_many(&self, keys: &[E::Key]) -> Result<Vec<E>, RepositoryError> {
+        let mut found = Vec::new();
+        for chunk in keys.chunks(repository::chunk_size(Self::MAX_PARAMETERS, E::KEY)) {
+            let statement = repository::find_many_statement::<E, Self>(chunk.len())?;
+            let binds: Vec<_> = chunk.iter().map(Bind::Key).collect();
+            found.extend(self.query_entities(&statement, &binds).await?);
+        }
+
+        Ok(found)
+    }
+
+    /// Indicates if the entity of the `key` exists.
+    async fn exists(&self, key: &E::Key) -> Result<bool, RepositoryError> {
+        let statement = format!(
+            "SELECT count(*) FROM {} WHERE {}",
+            Self::quote_identifier(E::TABLE)?,
+            repository::key_condition::<E, Self>(0)?
+        );
+        let count = self.query_i64(&statement, &[Bind::Key(key)]).await?;
+        Ok(count.unwrap_or_default() > 0)
+    }
+
+    /// Number of entities in the table.
+    async fn count(&self) -> Result<u64, RepositoryError> {
+        let statement = format!("SELECT count(*) FROM {}", Self::quote_identifier(E::TABLE)?);
+        let count = self.query_i64(&statement, &[]).await?;
+        Ok(count.unwrap_or_default() as u64)
+    }
+}
+
+/// Options applied when a transaction is opened
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TransactionOptions {
+    /// Instant the transaction must be finished by
+    deadline: Option<Instant>,
+    /// Configuration parameters scoped to the transaction
+    settings: Vec<(String, String)>,
+}
+
+impl TransactionOptions {
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[inline]
+    pub fn with_deadline(self, deadline: Instant) -> Self {
+        Self {
+            deadline: Some(deadline),
+            ..self
+        }
+    }
+
+    /// Sets a configuration parameter scoped to the transaction, e.g. `app.tenant_id` to be read
+    /// by row level security policies through `current_setting('app.tenant_id')`.
+    ///
+    /// The parameter keeps its value inside the save points of the transaction and is reset
+    /// when the transaction ends.
+    pub fn set_local(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.settings.push((name.into(), value.into()));
+        self
+    }
+
+    /// Instant the transaction must be finished by
+    pub fn deadline(&self) -> Option<Instant> {
+        self.deadline
+    }
+
+    /// Configuration parameters scoped to the transaction
+    pub fn settings(&self) -> &[(String, String)] {
+        &self.settings
+    }
+}
+
 #[derive(Debug, Clone, Default, Copy, PartialEq, Eq)]
 pub struct TransactionState {
     /// Indicates if transaction is open
@@ -39,6 +417,8 @@ pub struct TransactionState {
     ///
     /// Level 0 is the first
     depth: u32,
+    /// Instant the transaction must be finished by
+    deadline: Option<Instant>,
 }
 
 impl TransactionState {
@@ -49,7 +429,33 @@ impl TransactionState {
 
     #[inline]
     pub fn from_open_transaction(depth: u32) -> Self {
-        Self { open: true, depth }
+        Self {
+            open: true,
+            depth,
+            deadline: None,
+        }
+    }
+
+    /// State of a transaction opened with the `options`
+    ///
+    /// Only the deadline is kept, the settings are applied when the transaction begins.
+    #[inline]
+    pub fn from_options(depth: u32, options: &TransactionOptions) -> Self {
+        Self {
+            deadline: options.deadline,
+            ..Self::from_open_transaction(depth)
+        }
+    }
+
+    /// State of a save point created inside this transaction
+    ///
+    /// The save point inherits the deadline of the transaction.
+    #[inline]
+    pub fn nested(&self) -> Self {
+        Self {
+            depth: self.depth + 1,
+            ..*self
+        }
     }
 
     /// Indicates if transaction is open
@@ -61,36 +467,170 @@ impl TransactionState {
     pub fn depth(&self) -> u32 {
         self.depth
     }
+
+    /// Instant the transaction must be finished by
+    pub fn deadline(&self) -> Option<Instant> {
+        self.deadline
+    }
+
+    /// Time left until the deadline, `None` if the transaction has no deadline
+    pub fn remaining(&self) -> Option<Duration> {
+        self.deadline()
+            .map(|deadline| deadline.saturating_duration_since(Instant::now()))
+    }
+
+    /// Indicates if the deadline has passed
+    pub fn is_expired(&self) -> bool {
+        self.deadline()
+            .map_or(false, |deadline| deadline <= Instant::now())
+    }
 }
 
 pub type UnknownError = Box<dyn std::error::Error + Send + Sync + 'static>;
 
-#[cfg(any(feature = "pg_tokio", feature = "pg_deadpool"))]
+/// Error of the default `transaction_with`, for the units without transaction options
+pub(crate) fn unsupported_options() -> RepositoryError {
+    RepositoryError::Unknown("transaction options are not supported by this unit".into())
+}
+
+#[cfg(any(feature = "pg_tokio", feature = "pg_deadpool", feature = "pg_blocking"))]
 use tokio_postgres::error::DbError;
 
 #[derive(Debug)]
+#[allow(clippy::large_enum_variant)]
 pub enum RepositoryError {
-    #[cfg(any(feature = "pg_tokio", feature = "pg_deadpool"))]
+    #[cfg(any(feature = "pg_tokio", feature = "pg_deadpool", feature = "pg_blocking"))]
     TokioPostgres(DbError),
+    /// The transaction deadline has passed, or its running statement was cancelled
+    ///
+    /// Returned by a commit, the outcome of the transaction is unknown: the server may have
+    /// committed it before the cancel request arrived.
+    Timeout,
+    /// The transaction could not be serialized with concurrent ones and can be retried
+    ///
+    /// Holds the error reported by the database, if any.
+    SerializationFailure(Option<UnknownError>),
+    /// The connection to the database was lost
+    ConnectionClosed,
+    /// A lock requested without waiting is held by another transaction
+    LockNotAvailable,
+    /// An identifier (e.g. schema name) is not valid
+    InvalidIdentifier(String),
+    /// The entity was changed or deleted since its `expected` version was read
+    ConcurrencyConflict {
+        entity: &'static str,
+        id: String,
+        expected: i64,
+    },
+    #[cfg(all(
+        feature = "migrate",
+        any(feature = "pg_tokio", feature = "pg_deadpool")
+    ))]
+    Migration(migrate::MigrationError),
     Unknown(UnknownError),
 }
 
-#[cfg(any(feature = "pg_tokio", feature = "pg_deadpool"))]
+#[cfg(any(feature = "pg_tokio", feature = "pg_deadpool", feature = "pg_blocking"))]
 impl From<tokio_postgres::Error> for RepositoryError {
     fn from(err: tokio_postgres::Error) -> Self {
         if let Some(db_err) = err.as_db_error() {
+            if db_err.code() == &tokio_postgres::error::SqlState::T_R_SERIALIZATION_FAILURE {
+                return RepositoryError::SerializationFailure(Some(Box::new(db_err.clone())));
+            }
+            if db_err.code() == &tokio_postgres::error::SqlState::LOCK_NOT_AVAILABLE {
+                return RepositoryError::LockNotAvailable;
+            }
+            if db_err.code() == &tokio_postgres::error::SqlState::QUERY_CANCELED {
+                return RepositoryError::Timeout;
+            }
             return RepositoryError::TokioPostgres(db_err.clone());
         }
 
+        if err.is_closed() {
+            return RepositoryError::ConnectionClosed;
+        }
+
+        RepositoryError::Unknown(err.into())
+    }
+}
+
+#[cfg(feature = "pg_deadpool")]
+impl From<deadpool_postgres::PoolError> for RepositoryError {
+    fn from(err: deadpool_postgres::PoolError) -> Self {
+        match err {
+            deadpool_postgres::PoolError::Backend(err) => err.into(),
+            err => RepositoryError::Unknown(err.into()),
+        }
+    }
+}
+
+#[cfg(feature = "sqlite")]
+impl From<rusqlite::Error> for RepositoryError {
+    fn from(err: rusqlite::Error) -> Self {
+        if let rusqlite::Error::SqliteFailure(sqlite_err, _) = &err {
+            if let rusqlite::ErrorCode::DatabaseBusy | rusqlite::ErrorCode::DatabaseLocked =
+                sqlite_err.code
+            {
+                return RepositoryError::LockNotAvailable;
+            }
+        }
+
         RepositoryError::Unknown(err.into())
     }
 }
 
+#[cfg(feature = "sqlx")]
+impl From<sqlx_core::error::Error> for RepositoryError {
+    fn from(err: sqlx_core::error::Error) -> Self {
+        if let sqlx_core::error::Error::Database(db_err) = &err {
+            match db_err.code().as_deref() {
+                Some("40001") => return RepositoryError::SerializationFailure(Some(err.into())),
+                Some("55P03") => return RepositoryError::LockNotAvailable,
+                _ => {}
+            }
+        }
+
+        RepositoryError::Unknown(err.into())
+    }
+}
+
+mod repository;
+
+#[cfg(any(feature = "pg_tokio", feature = "pg_deadpool"))]
+pub mod pg;
+
 #[cfg(feature = "pg_tokio")]
 pub mod pg_tokio;
 
 #[cfg(feature = "pg_deadpool")]
 pub mod pg_deadpool;
 
+#[cfg(feature = "pg_blocking")]
+pub mod pg_blocking;
+
 #[cfg(feature = "sqlx")]
 pub mod sqlx;
+
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+
+#[cfg(feature = "mock")]
+pub mod mock;
+
+#[cfg(feature = "native_async")]
+pub mod native;
+
+#[cfg(any(feature = "pg_blocking", feature = "sqlite", feature = "r2d2_pool"))]
+pub mod blocking;
+
+#[cfg(feature = "fault")]
+pub mod fault;
+
+#[cfg(feature = "testing")]
+pub mod testing;
+
+#[cfg(all(
+    feature = "migrate",
+    any(feature = "pg_tokio", feature = "pg_deadpool")
+))]
+pub mod migrate;
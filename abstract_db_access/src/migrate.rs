@@ -0,0 +1,568 @@
+use std::{borrow::Cow, collections::BTreeMap, fs, path::Path};
+
+use super::{
+    pg::{AdvisoryKey, PgConnection, TransactionAdvisoryLock},
+    DbDriver, DbUnit, RepositoryError, TransactionUnit, Transactor,
+};
+
+/// Table keeping the applied migrations
+pub const HISTORY_TABLE: &str = "uow_migrations";
+
+/// Key of the transaction advisory lock taken by every migration
+const LOCK_KEY: AdvisoryKey = AdvisoryKey::from_name(HISTORY_TABLE);
+
+/// Embeds the migration at `$path`, made of the `$path.up.sql` and `$path.down.sql` files.
+///
+/// The paths are relative to the file invoking the macro, as in [`include_str`]. Migrations
+/// without a down script are declared with the trailing `irreversible` keyword.
+///
+/// ```ignore
+/// static MIGRATIONS: &[Migration] = &[
+///     migration!(1, "create_user", "migrations/0001_create_user"),
+///     migration!(2, "drop_legacy", "migrations/0002_drop_legacy", irreversible),
+/// ];
+/// ```
+#[macro_export]
+macro_rules! migration {
+    ($version:literal, $name:literal, $path:literal) => {
+        $crate::migrate::Migration::from_static(
+            $version,
+            $name,
+            include_str!(concat!($path, ".up.sql")),
+            Some(include_str!(concat!($path, ".down.sql"))),
+        )
+    };
+    ($version:literal, $name:literal, $path:literal, irreversible) => {
+        $crate::migrate::Migration::from_static(
+            $version,
+            $name,
+            include_str!(concat!($path, ".up.sql")),
+            None,
+        )
+    };
+}
+
+/// Migration failures, besides the database errors
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MigrationError {
+    /// Two migrations share the same version
+    DuplicateVersion(i64),
+    /// Migration names are limited to lowercase letters, digits and underscores
+    InvalidName(String),
+    /// The script of an applied migration was changed
+    ChecksumMismatch { version: i64, name: String },
+    /// An applied migration is not known by the migrator
+    UnknownApplied { version: i64, name: String },
+    /// The migration has no down script
+    Irreversible { version: i64, name: String },
+}
+
+impl From<MigrationError> for RepositoryError {
+    fn from(err: MigrationError) -> Self {
+        RepositoryError::Migration(err)
+    }
+}
+
+/// Versioned schema change, with the scripts to apply and revert it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Migration {
+    version: i64,
+    name: Cow<'static, str>,
+    up: Cow<'static, str>,
+    down: Option<Cow<'static, str>>,
+}
+
+impl Migration {
+    pub fn new(
+        version: i64,
+        name: impl Into<Cow<'static, str>>,
+        up: impl Into<Cow<'static, str>>,
+        down: Option<Cow<'static, str>>,
+    ) -> Self {
+        Self {
+            version,
+            name: name.into(),
+            up: up.into(),
+            down,
+        }
+    }
+
+    /// Creates a migration from embedded scripts, see [`migration!`].
+    pub const fn from_static(
+        version: i64,
+        name: &'static str,
+        up: &'static str,
+        down: Option<&'static str>,
+    ) -> Self {
+        Self {
+            version,
+            name: Cow::Borrowed(name),
+            up: Cow::Borrowed(up),
+            down: match down {
+                Some(down) => Some(Cow::Borrowed(down)),
+                None => None,
+            },
+        }
+    }
+
+    pub fn version(&self) -> i64 {
+        self.version
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn up(&self) -> &str {
+        &self.up
+    }
+
+    pub fn down(&self) -> Option<&str> {
+        self.down.as_deref()
+    }
+
+    /// Checksum of the up script, detecting changes made after the migration was applied
+    pub fn checksum(&self) -> String {
+        format!("{:016x}", AdvisoryKey::from_name(&self.up).value())
+    }
+}
+
+/// Loads the migrations of the `dir`, from the `<version>_<name>.up.sql` and
+/// `<version>_<name>.down.sql` files.
+///
+/// Files not following the naming are ignored, migrations without a down file are
+/// irreversible.
+pub fn load_dir(dir: impl AsRef<Path>) -> Result<Vec<Migration>, RepositoryError> {
+    let mut scripts: BTreeMap<(i64, String), (Option<String>, Option<String>)> = BTreeMap::new();
+
+    for entry in fs::read_dir(dir).map_err(|err| RepositoryError::Unknown(err.into()))? {
+        let path = entry
+            .map_err(|err| RepositoryError::Unknown(err.into()))?
+            .path();
+        let Some(file_name) = path.file_name().and_then(|name| name.to_str()) else {
+            continue;
+        };
+
+        let (stem, is_up) = if let Some(stem) = file_name.strip_suffix(".up.sql") {
+            (stem, true)
+        } else if let Some(stem) = file_name.strip_suffix(".down.sql") {
+            (stem, false)
+        } else {
+            continue;
+        };
+        let Some((version, name)) = stem.split_once('_') else {
+            continue;
+        };
+        let Ok(version) = version.parse::<i64>() else {
+            continue;
+        };
+
+        let script =
+            fs::read_to_string(&path).map_err(|err| RepositoryError::Unknown(err.into()))?;
+        let entry = scripts.entry((version, name.to_owned())).or_default();
+        if is_up {
+            entry.0 = Some(script);
+        } else {
+            entry.1 = Some(script);
+        }
+    }
+
+    scripts
+        .into_iter()
+        .map(|((version, name), (up, down))| match up {
+            Some(up) => Ok(Migration::new(version, name, up, down.map(Cow::Owned))),
+            None => Err(RepositoryError::Unknown(
+                format!("missing up script of migration {version}_{name}").into(),
+            )),
+        })
+        .collect()
+}
+
+/// State of a migration in the database
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MigrationState {
+    Pending,
+    Applied,
+    /// Applied, but its script was changed since
+    Modified,
+    /// Applied, but not known by the migrator
+    Unknown,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MigrationStatus {
+    pub version: i64,
+    pub name: String,
+    pub state: MigrationState,
+}
+
+/// Migration recorded in the history table
+struct AppliedMigration {
+    version: i64,
+    name: String,
+    checksum: String,
+}
+
+/// Applies and reverts the migrations, recording them in the [`HISTORY_TABLE`].
+///
+/// Each migration runs in a transaction of its own, along with its history record, so a failed
+/// migration leaves no trace. The transaction first takes an advisory lock, released with it
+/// even when the migration is cancelled, and checks the history again, so a migration handled
+/// meanwhile by a runner on another connection is skipped.
+///
+/// Runs on the Postgres units, whose statements it is written for.
+#[derive(Debug, Clone)]
+pub struct Migrator {
+    migrations: Vec<Migration>,
+}
+
+impl Migrator {
+    pub fn new(migrations: impl IntoIterator<Item = Migration>) -> Result<Self, RepositoryError> {
+        let mut migrations: Vec<Migration> = migrations.into_iter().collect();
+        migrations.sort_by_key(Migration::version);
+
+        for pair in migrations.windows(2) {
+            if pair[0].version == pair[1].version {
+                return Err(MigrationError::DuplicateVersion(pair[0].version).into());
+            }
+        }
+
+        // NOTE: the name is written as a literal in the history record
+        let invalid_name = migrations.iter().find(|migration| {
+            migration.name.is_empty()
+                || !migration
+                    .name
+                    .chars()
+                    .all(|ch| ch.is_ascii_lowercase() || ch.is_ascii_digit() || ch == '_')
+        });
+        if let Some(migration) = invalid_name {
+            return Err(MigrationError::InvalidName(migration.name.to_string()).into());
+        }
+
+        Ok(Self { migrations })
+    }
+
+    /// Known migrations, ordered by version
+    pub fn migrations(&self) -> &[Migration] {
+        &self.migrations
+    }
+
+    /// State of the known and applied migrations, ordered by version.
+    pub async fn status<U>(&self, unit: &mut U) -> Result<Vec<MigrationStatus>, RepositoryError>
+    where
+        U: PgConnection + DbDriver + Send,
+    {
+        let applied = applied_migrations(unit).await?;
+        Ok(self.compare(&applied))
+    }
+
+    /// Checks that every applied migration is known and unchanged.
+    pub async fn verify<U>(&self, unit: &mut U) -> Result<(), RepositoryError>
+    where
+        U: PgConnection + DbDriver + Send,
+    {
+        let applied = applied_migrations(unit).await?;
+        self.verify_applied(&applied)
+    }
+
+    /// Applies the pending migrations, returning their versions.
+    pub async fn up<U>(&self, unit: &mut U) -> Result<Vec<i64>, RepositoryError>
+    where
+        U: DbUnit + PgConnection + DbDriver + Send,
+        for<'t> <U as Transactor>::Transaction<'t>: TransactionAdvisoryLock + DbDriver,
+    {
+        create_history_table(unit).await?;
+        let applied = applied_migrations(unit).await?;
+        self.verify_applied(&applied)?;
+
+        let mut versions = Vec::new();
+        for migration in &self.migrations {
+            if applied
+                .iter()
+                .any(|applied| applied.version == migration.version)
+            {
+                continue;
+            }
+
+            let record = format!(
+                "INSERT INTO {HISTORY_TABLE} (version, name, checksum) VALUES ({}, '{}', '{}')",
+                migration.version,
+                migration.name,
+                migration.checksum(),
+            );
+            if run_locked(unit, migration.version, false, &migration.up, &record).await? {
+                versions.push(migration.version);
+            }
+        }
+
+        Ok(versions)
+    }
+
+    /// Reverts the last `steps` applied migrations, returning their versions.
+    pub async fn down<U>(&self, unit: &mut U, steps: usize) -> Result<Vec<i64>, RepositoryError>
+    where
+        U: DbUnit + PgConnection + DbDriver + Send,
+        for<'t> <U as Transactor>::Transaction<'t>: TransactionAdvisoryLock + DbDriver,
+    {
+        create_history_table(unit).await?;
+        let applied = applied_migrations(unit).await?;
+        self.verify_applied(&applied)?;
+
+        let mut versions = Vec::new();
+        for applied in applied.iter().rev().take(steps) {
+            let migration = self.find(applied.version).expect("verified migration");
+            let down = migration
+                .down()
+                .ok_or_else(|| MigrationError::Irreversible {
+                    version: migration.version,
+                    name: migration.name.to_string(),
+                })?;
+
+            let record = format!(
+                "DELETE FROM {HISTORY_TABLE} WHERE version = {}",
+                migration.version
+            );
+            if run_locked(unit, migration.version, true, down, &record).await? {
+                versions.push(migration.version);
+            }
+        }
+
+        Ok(versions)
+    }
+
+    fn find(&self, version: i64) -> Option<&Migration> {
+        self.migrations
+            .binary_search_by_key(&version, Migration::version)
+            .ok()
+            .map(|idx| &self.migrations[idx])
+    }
+
+    fn verify_applied(&self, applied: &[AppliedMigration]) -> Result<(), RepositoryError> {
+        for applied in applied {
+            match self.find(applied.version) {
+                None => {
+                    return Err(MigrationError::UnknownApplied {
+                        version: applied.version,
+                        name: applied.name.clone(),
+                    }
+                    .into())
+                }
+                Some(migration) if migration.checksum() != applied.checksum => {
+                    return Err(MigrationError::ChecksumMismatch {
+                        version: applied.version,
+                        name: applied.name.clone(),
+                    }
+                    .into())
+                }
+                Some(_) => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    fn compare(&self, applied: &[AppliedMigration]) -> Vec<MigrationStatus> {
+        let known = self.migrations.iter().map(|migration| {
+            let state = match applied.iter().find(|a| a.version == migration.version) {
+                None => MigrationState::Pending,
+                Some(applied) if applied.checksum != migration.checksum() => {
+                    MigrationState::Modified
+                }
+                Some(_) => MigrationState::Applied,
+            };
+            MigrationStatus {
+                version: migration.version,
+                name: migration.name.to_string(),
+                state,
+            }
+        });
+
+        let unknown = applied
+            .iter()
+            .filter(|applied| self.find(applied.version).is_none())
+            .map(|applied| MigrationStatus {
+                version: applied.version,
+                name: applied.name.clone(),
+                state: MigrationState::Unknown,
+            });
+
+        let mut status: Vec<MigrationStatus> = known.chain(unknown).collect();
+        status.sort_by_key(|status| status.version);
+        status
+    }
+}
+
+/// Creates the history table, if missing, under the advisory lock.
+async fn create_history_table<U>(unit: &mut U) -> Result<(), RepositoryError>
+where
+    U: DbUnit + Send,
+    for<'t> <U as Transactor>::Transaction<'t>: TransactionAdvisoryLock + DbDriver,
+{
+    let mut trx = unit.transaction().await?;
+    let res = async {
+        trx.advisory_lock(LOCK_KEY).await?;
+        trx.execute_script(&format!(
+            "CREATE TABLE IF NOT EXISTS {HISTORY_TABLE} (
+                version BIGINT PRIMARY KEY,
+                name TEXT NOT NULL,
+                checksum TEXT NOT NULL,
+                applied_at TIMESTAMPTZ NOT NULL DEFAULT now()
+            )"
+        ))
+        .await
+    }
+    .await;
+
+    finish(trx, res.map(|()| true)).await.map(drop)
+}
+
+/// Migrations recorded in the history table, ordered by version
+async fn applied_migrations<U: PgConnection + DbDriver + Send>(
+    unit: &mut U,
+) -> Result<Vec<AppliedMigration>, RepositoryError> {
+    let exists = unit
+        .query_text(&format!("SELECT to_regclass('{HISTORY_TABLE}')::text"))
+        .await?;
+    if exists[0][0].is_none() {
+        return Ok(Vec::new());
+    }
+
+    let rows = unit
+        .query_text(&format!(
+            "SELECT version::text, name, checksum FROM {HISTORY_TABLE} ORDER BY version"
+        ))
+        .await?;
+
+    rows.into_iter()
+        .map(|row| {
+            let mut values = row.into_iter().map(Option::unwrap_or_default);
+            let version = values.next().unwrap_or_default();
+            Ok(AppliedMigration {
+                version: version.parse().map_err(|_| {
+                    RepositoryError::Unknown(format!("invalid migration version {version}").into())
+                })?,
+                name: values.next().unwrap_or_default(),
+                checksum: values.next().unwrap_or_default(),
+            })
+        })
+        .collect()
+}
+
+/// Runs the `script` and the history `record` of the migration `version` in a transaction,
+/// holding the advisory lock.
+///
+/// The migration is skipped when the history no longer matches `applied`, having been handled
+/// by another runner, returning if it was run.
+async fn run_locked<U>(
+    unit: &mut U,
+    version: i64,
+    applied: bool,
+    script: &str,
+    record: &str,
+) -> Result<bool, RepositoryError>
+where
+    U: DbUnit + Send,
+    for<'t> <U as Transactor>::Transaction<'t>: TransactionAdvisoryLock + DbDriver,
+{
+    let mut trx = unit.transaction().await?;
+
+    let res = async {
+        trx.advisory_lock(LOCK_KEY).await?;
+        let rows = trx
+            .query_text(&format!(
+                "SELECT version::text FROM {HISTORY_TABLE} WHERE version = {version}"
+            ))
+            .await?;
+        if rows.is_empty() == applied {
+            return Ok(false);
+        }
+
+        trx.execute_script(script).await?;
+        trx.execute_script(record).await?;
+        Ok(true)
+    }
+    .await;
+
+    finish(trx, res).await
+}
+
+/// Commits the `trx` if `res` is a success, rolls it back otherwise.
+///
+/// A failed rollback is not reported over the error it follows, the server discards the
+/// transaction anyway when the connection is lost.
+async fn finish<T: TransactionUnit>(
+    trx: T,
+    res: Result<bool, RepositoryError>,
+) -> Result<bool, RepositoryError> {
+    match res {
+        Ok(run) => trx.commit().await.map(|()| run),
+        Err(err) => {
+            let _ = trx.rollback().await;
+            Err(err)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{path::PathBuf, process};
+
+    use super::*;
+
+    /// Directory of the `files`, removed when dropped
+    struct Dir(PathBuf);
+
+    impl Dir {
+        fn new(test: &str, files: &[(&str, &str)]) -> Self {
+            let dir = std::env::temp_dir().join(format!("uow-migrate-{}-{test}", process::id()));
+            fs::create_dir_all(&dir).unwrap();
+            for (name, script) in files {
+                fs::write(dir.join(name), script).unwrap();
+            }
+            Self(dir)
+        }
+    }
+
+    impl Drop for Dir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn loads_the_migrations_by_version() {
+        let dir = Dir::new(
+            "ordering",
+            &[
+                ("10_orders.up.sql", "CREATE TABLE orders ()"),
+                ("2_users.up.sql", "CREATE TABLE users ()"),
+                ("2_users.down.sql", "DROP TABLE users"),
+                ("1_init.up.sql", "SELECT 1"),
+                ("readme.md", "ignored"),
+                ("draft.up.sql", "ignored"),
+            ],
+        );
+
+        let migrations = load_dir(&dir.0).unwrap();
+
+        let loaded: Vec<_> = migrations
+            .iter()
+            .map(|migration| (migration.version(), migration.name(), migration.down()))
+            .collect();
+        assert_eq!(
+            loaded,
+            [
+                (1, "init", None),
+                (2, "users", Some("DROP TABLE users")),
+                (10, "orders", None),
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_migrations_without_up_script() {
+        let dir = Dir::new("missing_up", &[("1_init.down.sql", "SELECT 1")]);
+
+        assert!(matches!(load_dir(&dir.0), Err(RepositoryError::Unknown(_))));
+    }
+}
@@ -0,0 +1,391 @@
+use std::{
+    any::Any,
+    collections::{HashMap, HashSet},
+    fmt,
+    sync::{Arc, Mutex},
+};
+
+use async_trait::async_trait;
+
+#[cfg(feature = "native_async")]
+use super::native;
+use super::{
+    AutoCommit, DbAccess, DbUnit, InTransaction, RepositoryError, SavePoint, TransactionOptions,
+    TransactionState, TransactionUnit, Transactor,
+};
+
+/// Rows of a table, type erased so tables of different row types can be stored together
+trait TableData: Any + Send + Sync {
+    fn clone_box(&self) -> Box<dyn TableData>;
+    fn len(&self) -> usize;
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+impl<R: Clone + Send + Sync + 'static> TableData for Vec<R> {
+    fn clone_box(&self) -> Box<dyn TableData> {
+        Box::new(self.clone())
+    }
+
+    fn len(&self) -> usize {
+        Vec::len(self)
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+/// In-memory tables, each one holding rows of a single type.
+///
+/// Accessing a table with a row type other than the one it was created with panics.
+#[derive(Default)]
+pub struct Tables {
+    tables: HashMap<String, Box<dyn TableData>>,
+    /// Tables accessed mutably, applied by the commit of a transaction
+    written: HashSet<String>,
+}
+
+impl Tables {
+    /// Rows of the table, empty if the table was never written
+    pub fn rows<R: Clone + Send + Sync + 'static>(&self, table: &str) -> &[R] {
+        match self.tables.get(table) {
+            Some(data) => data
+                .as_any()
+                .downcast_ref::<Vec<R>>()
+                .unwrap_or_else(|| panic!("table `{table}` holds rows of another type")),
+            None => &[],
+        }
+    }
+
+    /// Rows of the table, created empty if the table was never written
+    pub fn rows_mut<R: Clone + Send + Sync + 'static>(&mut self, table: &str) -> &mut Vec<R> {
+        self.written.insert(table.to_owned());
+        self.tables
+            .entry(table.to_owned())
+            .or_insert_with(|| Box::new(Vec::<R>::new()))
+            .as_any_mut()
+            .downcast_mut::<Vec<R>>()
+            .unwrap_or_else(|| panic!("table `{table}` holds rows of another type"))
+    }
+
+    /// Removes all the tables.
+    pub fn clear(&mut self) {
+        self.written
+            .extend(self.tables.drain().map(|(name, _)| name));
+    }
+
+    /// Moves the tables written in `other` into these ones.
+    fn apply(&mut self, mut other: Tables) {
+        for name in other.written.drain() {
+            match other.tables.remove(&name) {
+                Some(data) => self.tables.insert(name.clone(), data),
+                None => self.tables.remove(&name),
+            };
+            self.written.insert(name);
+        }
+    }
+}
+
+impl Clone for Tables {
+    fn clone(&self) -> Self {
+        Self {
+            tables: self
+                .tables
+                .iter()
+                .map(|(name, data)| (name.clone(), data.clone_box()))
+                .collect(),
+            written: self.written.clone(),
+        }
+    }
+}
+
+impl fmt::Debug for Tables {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map()
+            .entries(self.tables.iter().map(|(name, data)| (name, data.len())))
+            .finish()
+    }
+}
+
+/// Committed tables, along with the number of times each one was written
+#[derive(Debug, Default)]
+struct Committed {
+    tables: Tables,
+    versions: HashMap<String, u64>,
+}
+
+impl Committed {
+    /// Bumps the version of the tables written since the last call.
+    fn bump_written(&mut self) {
+        for name in self.tables.written.drain() {
+            *self.versions.entry(name).or_default() += 1;
+        }
+    }
+}
+
+/// In-memory database shared by the mock units.
+///
+/// Holds the committed state of the tables, cloning it gives access to the same database.
+#[derive(Debug, Clone, Default)]
+pub struct MockDb {
+    committed: Arc<Mutex<Committed>>,
+}
+
+impl MockDb {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a unit accessing this database.
+    pub fn unit(&self) -> MockUnit {
+        MockUnit { db: self.clone() }
+    }
+
+    /// Copy of the committed tables
+    pub fn snapshot(&self) -> Tables {
+        self.committed.lock().unwrap().tables.clone()
+    }
+}
+
+/// Unit or transaction backed by the in-memory tables.
+///
+/// The repositories of the mock backend are implemented through these accessors, the same way
+/// the Postgres ones are implemented through the client.
+pub trait MockConnection {
+    /// Reads the tables visible to this unit or transaction.
+    fn read<T>(&self, f: impl FnOnce(&Tables) -> T) -> T;
+
+    /// Writes the tables, applied right away on a unit and buffered until commit on a
+    /// transaction.
+    fn write<T>(&mut self, f: impl FnOnce(&mut Tables) -> T) -> T;
+
+    /// Indicates if the statements run inside a transaction
+    fn is_transaction(&self) -> bool;
+}
+
+/// Unit operating directly on the committed tables of a [`MockDb`]
+#[derive(Debug, Clone)]
+pub struct MockUnit {
+    db: MockDb,
+}
+
+impl MockUnit {
+    pub fn db(&self) -> &MockDb {
+        &self.db
+    }
+}
+
+/// Tables a transaction applies its changes to on commit
+#[derive(Debug)]
+enum Target<'t> {
+    Db(&'t MockDb),
+    SavePoint(&'t mut Tables),
+}
+
+/// Transaction or save point over the in-memory tables.
+///
+/// Works on a copy of the tables taken when opened: commit applies the tables it wrote to the
+/// database, or to the enclosing transaction for a save point, and rollback discards them.
+/// Dropping the transaction without commit rolls it back.
+///
+/// The commit fails with [`RepositoryError::SerializationFailure`] when a table it wrote was
+/// committed by another unit since the transaction was opened.
+#[derive(Debug)]
+pub struct MockTrxUnit<'t> {
+    target: Target<'t>,
+    tables: Tables,
+    /// Versions of the committed tables when the transaction was opened
+    versions: HashMap<String, u64>,
+    pub state: TransactionState,
+}
+
+impl MockConnection for MockUnit {
+    fn read<T>(&self, f: impl FnOnce(&Tables) -> T) -> T {
+        f(&self.db.committed.lock().unwrap().tables)
+    }
+
+    fn write<T>(&mut self, f: impl FnOnce(&mut Tables) -> T) -> T {
+        let mut committed = self.db.committed.lock().unwrap();
+        let res = f(&mut committed.tables);
+        committed.bump_written();
+        res
+    }
+
+    fn is_transaction(&self) -> bool {
+        false
+    }
+}
+
+impl DbAccess for MockUnit {
+    type Connection = MockDb;
+}
+
+impl Transactor for MockUnit {
+    type Transaction<'t> = MockTrxUnit<'t>;
+}
+
+impl AutoCommit for MockUnit {}
+
+#[async_trait]
+impl DbUnit for MockUnit {
+    async fn transaction<'s>(&'s mut self) -> Result<Self::Transaction<'s>, RepositoryError> {
+        self.transaction_with(TransactionOptions::new()).await
+    }
+
+    async fn transaction_with<'s>(
+        &'s mut self,
+        options: TransactionOptions,
+    ) -> Result<Self::Transaction<'s>, RepositoryError> {
+        Ok(begin_with(self, options))
+    }
+}
+
+#[cfg(feature = "native_async")]
+impl native::Transactor for MockUnit {
+    type Transaction<'t> = MockTrxUnit<'t>;
+}
+
+#[cfg(feature = "native_async")]
+impl native::DbUnit for MockUnit {
+    async fn transaction<'s>(&'s mut self) -> Result<MockTrxUnit<'s>, RepositoryError> {
+        Ok(begin_with(self, TransactionOptions::new()))
+    }
+
+    async fn transaction_with<'s>(
+        &'s mut self,
+        options: TransactionOptions,
+    ) -> Result<MockTrxUnit<'s>, RepositoryError> {
+        Ok(begin_with(self, options))
+    }
+}
+
+impl<'t> MockConnection for MockTrxUnit<'t> {
+    fn read<T>(&self, f: impl FnOnce(&Tables) -> T) -> T {
+        f(&self.tables)
+    }
+
+    fn write<T>(&mut self, f: impl FnOnce(&mut Tables) -> T) -> T {
+        f(&mut self.tables)
+    }
+
+    fn is_transaction(&self) -> bool {
+        true
+    }
+}
+
+impl<'t> DbAccess for MockTrxUnit<'t> {
+    type Connection = MockDb;
+}
+
+impl<'t> Transactor for MockTrxUnit<'t> {
+    type Transaction<'trx> = MockTrxUnit<'trx>;
+}
+
+impl<'t> InTransaction for MockTrxUnit<'t> {}
+
+#[async_trait]
+impl<'t> TransactionUnit for MockTrxUnit<'t> {
+    async fn commit(self) -> Result<(), RepositoryError> {
+        commit(self)
+    }
+
+    async fn rollback(self) -> Result<(), RepositoryError> {
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl<'t> SavePoint for MockTrxUnit<'t> {
+    async fn save_point<'s>(
+        &'s mut self,
+        _name: &str,
+    ) -> Result<Self::Transaction<'s>, RepositoryError> {
+        save_point(self)
+    }
+
+    fn depth(&self) -> u32 {
+        self.state.depth()
+    }
+}
+
+#[cfg(feature = "native_async")]
+impl<'t> native::Transactor for MockTrxUnit<'t> {
+    type Transaction<'trx> = MockTrxUnit<'trx>;
+}
+
+#[cfg(feature = "native_async")]
+impl<'t> native::TransactionUnit for MockTrxUnit<'t> {
+    async fn commit(self) -> Result<(), RepositoryError> {
+        commit(self)
+    }
+
+    async fn rollback(self) -> Result<(), RepositoryError> {
+        Ok(())
+    }
+}
+
+#[cfg(feature = "native_async")]
+impl<'t> native::SavePoint for MockTrxUnit<'t> {
+    async fn save_point<'s>(&'s mut self, _name: &str) -> Result<MockTrxUnit<'s>, RepositoryError> {
+        save_point(self)
+    }
+
+    fn depth(&self) -> u32 {
+        self.state.depth()
+    }
+}
+
+fn begin_with(unit: &mut MockUnit, options: TransactionOptions) -> MockTrxUnit<'_> {
+    let committed = unit.db.committed.lock().unwrap();
+    MockTrxUnit {
+        tables: committed.tables.clone(),
+        versions: committed.versions.clone(),
+        target: Target::Db(&unit.db),
+        state: TransactionState::from_options(0, &options),
+    }
+}
+
+fn commit(trx: MockTrxUnit<'_>) -> Result<(), RepositoryError> {
+    if trx.state.is_expired() {
+        return Err(RepositoryError::Timeout);
+    }
+
+    match trx.target {
+        Target::Db(db) => {
+            let mut committed = db.committed.lock().unwrap();
+            let conflict = trx
+                .tables
+                .written
+                .iter()
+                .any(|name| committed.versions.get(name) != trx.versions.get(name));
+            if conflict {
+                return Err(RepositoryError::SerializationFailure(None));
+            }
+
+            committed.tables.apply(trx.tables);
+            committed.bump_written();
+        }
+        Target::SavePoint(tables) => tables.apply(trx.tables),
+    }
+    Ok(())
+}
+
+fn save_point<'s>(trx: &'s mut MockTrxUnit<'_>) -> Result<MockTrxUnit<'s>, RepositoryError> {
+    if trx.state.is_expired() {
+        return Err(RepositoryError::Timeout);
+    }
+
+    let mut tables = trx.tables.clone();
+    tables.written.clear();
+    Ok(MockTrxUnit {
+        state: trx.state.nested(),
+        tables,
+        versions: HashMap::new(),
+        target: Target::SavePoint(&mut trx.tables),
+    })
+}
@@ -0,0 +1,65 @@
+//! Unit of work traits using native async functions in traits.
+//!
+//! Mirrors [`DbUnit`](crate::DbUnit), [`TransactionUnit`](crate::TransactionUnit) and
+//! [`SavePoint`](crate::SavePoint) without `async_trait`: the futures are not boxed, so opening
+//! and finishing a transaction does not allocate, and are not required to be `Send`, so the
+//! units can be driven by single threaded executors (e.g. `tokio::task::LocalSet`).
+//!
+//! Requires rust 1.75, enable the `native_async` feature when building with it.
+
+use std::{future::Future, time::Instant};
+
+use super::{unsupported_options, DbAccess, RepositoryError, TransactionOptions};
+
+pub trait Transactor {
+    type Transaction<'t>: TransactionUnit;
+}
+
+pub trait DbUnit: DbAccess + Transactor {
+    /// Creates a new transaction.
+    fn transaction<'s>(
+        &'s mut self,
+    ) -> impl Future<Output = Result<Self::Transaction<'s>, RepositoryError>>;
+
+    /// Creates a new transaction configured by the `options`.
+    ///
+    /// The default implementation only accepts the default options.
+    fn transaction_with<'s>(
+        &'s mut self,
+        options: TransactionOptions,
+    ) -> impl Future<Output = Result<Self::Transaction<'s>, RepositoryError>> {
+        async move {
+            if options != TransactionOptions::default() {
+                return Err(unsupported_options());
+            }
+            self.transaction().await
+        }
+    }
+
+    /// Creates a new transaction that must be finished before the `deadline`.
+    ///
+    /// When the deadline passes, the running query is cancelled, the transaction is rolled back
+    /// and [`RepositoryError::Timeout`] is returned.
+    fn transaction_with_deadline<'s>(
+        &'s mut self,
+        deadline: Instant,
+    ) -> impl Future<Output = Result<Self::Transaction<'s>, RepositoryError>> {
+        self.transaction_with(TransactionOptions::new().with_deadline(deadline))
+    }
+}
+
+/// Transaction of a unit, consumed by commit or rollback.
+pub trait TransactionUnit: DbAccess + Transactor + Sized {
+    fn commit(self) -> impl Future<Output = Result<(), RepositoryError>>;
+    fn rollback(self) -> impl Future<Output = Result<(), RepositoryError>>;
+}
+
+pub trait SavePoint: TransactionUnit {
+    fn save_point<'s>(
+        &'s mut self,
+        name: &str,
+    ) -> impl Future<Output = Result<Self::Transaction<'s>, RepositoryError>>;
+
+    /// Returns the nested level
+    fn depth(&self) -> u32;
+}
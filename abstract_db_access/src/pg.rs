@@ -0,0 +1,355 @@
+use std::{fmt, future::Future, pin::Pin, sync::Arc, time::Instant};
+
+use async_trait::async_trait;
+use futures_util::{stream, TryStreamExt};
+use tokio_postgres::{
+    tls::{MakeTlsConnect, TlsConnect},
+    types::ToSql,
+    CancelToken, Client, GenericClient, NoTls, SimpleQueryMessage, Socket, Transaction,
+};
+
+use super::{DbAccess, DbDriver, RepositoryError, RowStream};
+
+mod advisory;
+mod copy;
+mod cursor;
+mod notify;
+mod pipeline;
+mod repository;
+mod row;
+
+pub use advisory::{AdvisoryKey, AdvisoryLockGuard, SessionAdvisoryLock, TransactionAdvisoryLock};
+pub use copy::{BulkCopy, CopyRow};
+pub use cursor::TransactionCursor;
+pub use notify::{Listener, TransactionNotify};
+pub use pipeline::{Pipeline, PipelineOutput, Pipelined};
+pub use repository::{PgEntity, RowLock, TransactionRowLock};
+pub use row::{has_column, FromRow, Row};
+
+/// Postgres limit for identifiers length (NAMEDATALEN - 1)
+const MAX_IDENTIFIER_LEN: usize = 63;
+
+/// Wait for a connection of the pool to cancel the query through, before falling back to a
+/// cancel request
+#[cfg(feature = "pg_deadpool")]
+const POOL_CANCEL_WAIT: std::time::Duration = std::time::Duration::from_millis(100);
+
+/// Statement along with its parameters
+pub type PgQuery<'q> = (&'q str, &'q [&'q (dyn ToSql + Sync)]);
+
+/// Unit or transaction backed by a Postgres client
+pub trait PgConnection {
+    type Client: GenericClient + Sync;
+
+    /// Client the statements are executed on
+    fn pg_client(&self) -> &Self::Client;
+
+    /// Indicates if the statements run inside a transaction
+    fn is_transaction(&self) -> bool;
+}
+
+type CancelFuture = Pin<Box<dyn Future<Output = Result<(), tokio_postgres::Error>> + Send>>;
+
+/// Connects to the server to send the cancel requests of the queries.
+///
+/// Defaults to a plain connection, a server requiring TLS needs the connector the unit was
+/// connected with.
+#[derive(Clone)]
+pub struct CancelConnector(Arc<dyn Fn(CancelToken) -> CancelFuture + Send + Sync>);
+
+impl CancelConnector {
+    pub fn new<T>(tls: T) -> Self
+    where
+        T: MakeTlsConnect<Socket> + Clone + Send + Sync + 'static,
+        T::Stream: Send,
+        T::TlsConnect: Send,
+        <T::TlsConnect as TlsConnect<Socket>>::Future: Send,
+    {
+        Self(Arc::new(move |token| {
+            let tls = tls.clone();
+            Box::pin(async move { token.cancel_query(tls).await })
+        }))
+    }
+}
+
+impl Default for CancelConnector {
+    fn default() -> Self {
+        Self::new(NoTls)
+    }
+}
+
+impl fmt::Debug for CancelConnector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CancelConnector").finish_non_exhaustive()
+    }
+}
+
+/// How the running query of a connection is cancelled
+#[derive(Clone)]
+enum Cancel {
+    /// Cancel request sent on a new connection
+    Token(CancelToken, CancelConnector),
+    /// `pg_cancel_backend` run on another connection of the pool, which knows how to connect,
+    /// or a cancel request without TLS when no connection is available in time
+    #[cfg(feature = "pg_deadpool")]
+    Pool(deadpool_postgres::Pool, i32, CancelToken),
+}
+
+/// Deadline of a Postgres transaction.
+///
+/// Detached from the transaction, so it can bound futures that borrow the transaction mutably,
+/// like the repository methods.
+#[derive(Clone)]
+pub struct Deadline {
+    cancel: Cancel,
+    deadline: Option<Instant>,
+}
+
+impl Deadline {
+    pub(crate) fn new(
+        token: CancelToken,
+        connector: CancelConnector,
+        deadline: Option<Instant>,
+    ) -> Self {
+        Self {
+            cancel: Cancel::Token(token, connector),
+            deadline,
+        }
+    }
+
+    /// Cancels through the connection of the `pool` the query of the `pid` backend, or through
+    /// the `token` when the pool has no connection to spare.
+    #[cfg(feature = "pg_deadpool")]
+    pub(crate) fn with_pool(
+        pool: deadpool_postgres::Pool,
+        pid: i32,
+        token: CancelToken,
+        deadline: Option<Instant>,
+    ) -> Self {
+        Self {
+            cancel: Cancel::Pool(pool, pid, token),
+            deadline,
+        }
+    }
+
+    /// Instant the transaction must be finished by
+    pub fn instant(&self) -> Option<Instant> {
+        self.deadline
+    }
+
+    /// Drives `fut` until the deadline, if any.
+    ///
+    /// When the deadline passes, `fut` is dropped and a cancel request is sent to the server
+    /// to stop the query that may still be running on the connection.
+    pub async fn run<F, T, E>(&self, fut: F) -> Result<T, RepositoryError>
+    where
+        F: Future<Output = Result<T, E>>,
+        E: Into<RepositoryError>,
+    {
+        let deadline = match self.deadline {
+            Some(deadline) => deadline,
+            None => return fut.await.map_err(Into::into),
+        };
+
+        match tokio::time::timeout_at(deadline.into(), fut).await {
+            Ok(res) => res.map_err(Into::into),
+            Err(_) => {
+                self.cancel().await;
+                Err(RepositoryError::Timeout)
+            }
+        }
+    }
+
+    /// Cancels the queries still running on the connection when the deadline passes, until the
+    /// returned watchdog is dropped.
+    ///
+    /// Bounds the statements the repositories run directly on the client, which
+    /// [`Deadline::run`] does not wrap. The cancelled statement fails with
+    /// [`RepositoryError::Timeout`].
+    pub(crate) fn watch(&self) -> Option<Watchdog> {
+        let deadline = self.deadline?;
+        let this = self.clone();
+        let task = tokio::spawn(async move {
+            tokio::time::sleep_until(deadline.into()).await;
+            this.cancel().await;
+        });
+        Some(Watchdog(task))
+    }
+
+    async fn cancel(&self) {
+        // NOTE: cancellation is best effort, the timeout is reported even if the cancel request
+        // could not reach the server.
+        match &self.cancel {
+            Cancel::Token(token, connector) => {
+                let _ = (connector.0)(token.clone()).await;
+            }
+            #[cfg(feature = "pg_deadpool")]
+            Cancel::Pool(pool, pid, token) => {
+                // NOTE: the pool may be exhausted, e.g. of size one with its connection held by
+                // the transaction being cancelled, its TLS configuration is then out of reach
+                match tokio::time::timeout(POOL_CANCEL_WAIT, pool.get()).await {
+                    Ok(Ok(client)) => {
+                        let _ = client.execute("SELECT pg_cancel_backend($1)", &[pid]).await;
+                    }
+                    _ => {
+                        let _ = token.cancel_query(NoTls).await;
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Debug for Deadline {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Deadline")
+            .field("deadline", &self.deadline)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Task cancelling the running query at the deadline, aborted when dropped with the transaction
+#[derive(Debug)]
+pub(crate) struct Watchdog(tokio::task::JoinHandle<()>);
+
+impl Drop for Watchdog {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+/// Streams the rows of the `query` executed by the `client`.
+pub(crate) fn query_stream<'s, C>(client: &'s C, query: PgQuery<'s>) -> RowStream<'s, Row>
+where
+    C: GenericClient + Sync,
+{
+    let (sql, params) = query;
+    let rows = client.query_raw(sql, params.iter().copied());
+    Box::pin(
+        stream::once(rows)
+            .map_ok(|rows| rows.map_err(RepositoryError::from))
+            .map_err(RepositoryError::from)
+            .try_flatten(),
+    )
+}
+
+#[async_trait]
+impl<T: PgConnection + DbAccess + Send + Sync> DbDriver for T {
+    async fn execute_script(&mut self, sql: &str) -> Result<(), RepositoryError> {
+        self.pg_client().client().batch_execute(sql).await?;
+        Ok(())
+    }
+
+    async fn query_text(&mut self, sql: &str) -> Result<Vec<Vec<Option<String>>>, RepositoryError> {
+        let messages = self.pg_client().client().simple_query(sql).await?;
+        let rows = messages
+            .into_iter()
+            .filter_map(|message| match message {
+                SimpleQueryMessage::Row(row) => Some(
+                    (0..row.len())
+                        .map(|idx| row.get(idx).map(ToOwned::to_owned))
+                        .collect(),
+                ),
+                _ => None,
+            })
+            .collect();
+        Ok(rows)
+    }
+}
+
+/// Begins a transaction with the configuration parameters scoped to it.
+pub(crate) async fn begin<'c>(
+    client: &'c mut Client,
+    settings: &[(String, String)],
+) -> Result<Transaction<'c>, tokio_postgres::Error> {
+    let trx = client.transaction().await?;
+
+    if !settings.is_empty() {
+        let (names, values): (Vec<&str>, Vec<&str>) = settings
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+            .unzip();
+
+        trx.execute(
+            "SELECT set_config(name, value, true) FROM unnest($1::text[], $2::text[]) AS s(name, value)",
+            &[&names, &values],
+        )
+        .await?;
+    }
+
+    Ok(trx)
+}
+
+/// Validates `name` as a lowercase unquoted identifier.
+///
+/// Starting with a letter or underscore and followed by letters, digits, underscores or dollar
+/// signs.
+pub(crate) fn validate_identifier(name: &str) -> Result<(), RepositoryError> {
+    let mut chars = name.chars();
+    let valid_start = chars
+        .next()
+        .map_or(false, |ch| ch.is_ascii_lowercase() || ch == '_');
+    let valid_rest =
+        chars.all(|ch| ch.is_ascii_lowercase() || ch.is_ascii_digit() || ch == '_' || ch == '$');
+
+    if !valid_start || !valid_rest || name.len() > MAX_IDENTIFIER_LEN {
+        return Err(RepositoryError::InvalidIdentifier(name.to_owned()));
+    }
+
+    Ok(())
+}
+
+/// Validates and quotes a, possibly schema qualified, identifier (e.g. `public.user`).
+pub(crate) fn quote_identifier(name: &str) -> Result<String, RepositoryError> {
+    let mut quoted = String::with_capacity(name.len() + 4);
+    for (idx, part) in name.split('.').enumerate() {
+        validate_identifier(part)?;
+        if idx > 0 {
+            quoted.push('.');
+        }
+        quoted.push('"');
+        quoted.push_str(part);
+        quoted.push('"');
+    }
+    Ok(quoted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn is_valid(name: &str) -> bool {
+        validate_identifier(name).is_ok()
+    }
+
+    #[test]
+    fn accepts_lowercase_identifiers() {
+        assert!(is_valid("tenant_1"));
+        assert!(is_valid("_tenant$a"));
+        assert!(is_valid(&"a".repeat(MAX_IDENTIFIER_LEN)));
+    }
+
+    #[test]
+    fn rejects_invalid_identifiers() {
+        assert!(!is_valid(""));
+        assert!(!is_valid("1tenant"));
+        assert!(!is_valid("$tenant"));
+        assert!(!is_valid("Tenant"));
+        assert!(!is_valid("tenant-a"));
+        assert!(!is_valid("tenant\"; DROP SCHEMA public; --"));
+        assert!(!is_valid("ténant"));
+        assert!(!is_valid(&"a".repeat(MAX_IDENTIFIER_LEN + 1)));
+    }
+
+    #[test]
+    fn quotes_qualified_identifiers() {
+        assert_eq!(
+            quote_identifier("public.user").unwrap(),
+            r#""public"."user""#
+        );
+        assert!(matches!(
+            quote_identifier("public."),
+            Err(RepositoryError::InvalidIdentifier(name)) if name.is_empty()
+        ));
+    }
+}
@@ -0,0 +1,214 @@
+use std::ops::{Deref, DerefMut};
+
+use async_trait::async_trait;
+use futures_util::FutureExt;
+use tokio_postgres::GenericClient;
+
+use super::PgConnection;
+use crate::{DbUnit, RepositoryError, TransactionUnit};
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0100_0000_01b3;
+
+/// Key of a Postgres advisory lock
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct AdvisoryKey(i64);
+
+impl AdvisoryKey {
+    #[inline]
+    pub const fn new(key: i64) -> Self {
+        Self(key)
+    }
+
+    /// Derives the key from a name.
+    ///
+    /// Uses the FNV-1a hash, so the same name maps to the same key across builds, platforms and
+    /// processes that share the lock.
+    pub const fn from_name(name: &str) -> Self {
+        let bytes = name.as_bytes();
+        let mut hash = FNV_OFFSET_BASIS;
+        let mut idx = 0;
+        while idx < bytes.len() {
+            hash ^= bytes[idx] as u64;
+            hash = hash.wrapping_mul(FNV_PRIME);
+            idx += 1;
+        }
+        Self(hash as i64)
+    }
+
+    pub fn value(&self) -> i64 {
+        self.0
+    }
+}
+
+impl From<i64> for AdvisoryKey {
+    fn from(key: i64) -> Self {
+        Self::new(key)
+    }
+}
+
+impl From<&str> for AdvisoryKey {
+    fn from(name: &str) -> Self {
+        Self::from_name(name)
+    }
+}
+
+/// Advisory locks held until the end of the transaction.
+///
+/// Only available on transactions, the locks are released by the server at commit or rollback.
+#[async_trait]
+pub trait TransactionAdvisoryLock: PgConnection + TransactionUnit + Sync {
+    /// Waits until the lock is acquired.
+    async fn advisory_lock(&self, key: AdvisoryKey) -> Result<(), RepositoryError> {
+        self.pg_client()
+            .execute("SELECT pg_advisory_xact_lock($1)", &[&key.0])
+            .await?;
+        Ok(())
+    }
+
+    /// Acquires the lock if available, returning `false` otherwise.
+    async fn try_advisory_lock(&self, key: AdvisoryKey) -> Result<bool, RepositoryError> {
+        let row = self
+            .pg_client()
+            .query_one("SELECT pg_try_advisory_xact_lock($1)", &[&key.0])
+            .await?;
+        Ok(row.get(0))
+    }
+}
+
+impl<T: PgConnection + TransactionUnit + Sync> TransactionAdvisoryLock for T {}
+
+/// Advisory locks held by the connection session.
+///
+/// Only available on units, the lock is released when the returned guard is dropped.
+#[async_trait]
+pub trait SessionAdvisoryLock: PgConnection + DbUnit + Send + Sync + Sized {
+    /// Waits until the lock is acquired.
+    async fn advisory_lock(
+        &mut self,
+        key: AdvisoryKey,
+    ) -> Result<AdvisoryLockGuard<'_, Self>, RepositoryError> {
+        self.pg_client()
+            .execute("SELECT pg_advisory_lock($1)", &[&key.0])
+            .await?;
+        Ok(AdvisoryLockGuard::new(self, key))
+    }
+
+    /// Acquires the lock if available, returning `None` otherwise.
+    async fn try_advisory_lock(
+        &mut self,
+        key: AdvisoryKey,
+    ) -> Result<Option<AdvisoryLockGuard<'_, Self>>, RepositoryError> {
+        let row = self
+            .pg_client()
+            .query_one("SELECT pg_try_advisory_lock($1)", &[&key.0])
+            .await?;
+
+        if row.get(0) {
+            Ok(Some(AdvisoryLockGuard::new(self, key)))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+impl<T: PgConnection + DbUnit + Send + Sync> SessionAdvisoryLock for T {}
+
+/// Session advisory lock, released on drop.
+///
+/// Gives access to the locked unit, so the work protected by the lock can be made through it.
+pub struct AdvisoryLockGuard<'u, U: PgConnection> {
+    unit: &'u mut U,
+    key: AdvisoryKey,
+    /// Indicates if [`AdvisoryLockGuard::unlock`] released the lock, disarming the drop
+    unlocked: bool,
+}
+
+impl<'u, U: PgConnection> AdvisoryLockGuard<'u, U> {
+    fn new(unit: &'u mut U, key: AdvisoryKey) -> Self {
+        Self {
+            unit,
+            key,
+            unlocked: false,
+        }
+    }
+
+    pub fn key(&self) -> AdvisoryKey {
+        self.key
+    }
+
+    /// Releases the lock, waiting for the server response.
+    ///
+    /// Returns `false` if the lock was not held by the session. The guard stays armed until the
+    /// server answers, so a failed or cancelled unlock is still sent by the drop.
+    pub async fn unlock(mut self) -> Result<bool, RepositoryError> {
+        let row = self
+            .unit
+            .pg_client()
+            .query_one("SELECT pg_advisory_unlock($1)", &[&self.key.0])
+            .await?;
+        self.unlocked = true;
+        Ok(row.get(0))
+    }
+}
+
+impl<'u, U: PgConnection> Drop for AdvisoryLockGuard<'u, U> {
+    fn drop(&mut self) {
+        if self.unlocked {
+            return;
+        }
+
+        // NOTE: the unlock is sent on the first poll, without waiting for the server response,
+        // the same way `tokio_postgres::Transaction` sends the rollback when dropped.
+        let unlock = format!("SELECT pg_advisory_unlock({})", self.key.0);
+        let _ = self
+            .unit
+            .pg_client()
+            .client()
+            .batch_execute(&unlock)
+            .now_or_never();
+    }
+}
+
+impl<'u, U: PgConnection> Deref for AdvisoryLockGuard<'u, U> {
+    type Target = U;
+
+    fn deref(&self) -> &Self::Target {
+        self.unit
+    }
+}
+
+impl<'u, U: PgConnection> DerefMut for AdvisoryLockGuard<'u, U> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.unit
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hashes_names_with_fnv1a() {
+        assert_eq!(
+            AdvisoryKey::from_name(""),
+            AdvisoryKey(0xcbf2_9ce4_8422_2325_u64 as i64)
+        );
+        assert_eq!(
+            AdvisoryKey::from_name("a"),
+            AdvisoryKey(0xaf63_dc4c_8601_ec8c_u64 as i64)
+        );
+        assert_eq!(
+            AdvisoryKey::from_name("foobar"),
+            AdvisoryKey(0x8594_4171_f739_67e8_u64 as i64)
+        );
+    }
+
+    #[test]
+    fn derives_keys_at_compile_time() {
+        const KEY: AdvisoryKey = AdvisoryKey::from_name("migrations");
+
+        assert_eq!(KEY, AdvisoryKey::from("migrations"));
+        assert_ne!(KEY, AdvisoryKey::from("migration"));
+    }
+}
@@ -0,0 +1,77 @@
+use async_trait::async_trait;
+use futures_util::{pin_mut, Stream, StreamExt};
+use tokio_postgres::{
+    binary_copy::BinaryCopyInWriter,
+    types::{ToSql, Type},
+    GenericClient,
+};
+
+use super::{quote_identifier, PgConnection};
+use crate::RepositoryError;
+
+/// Row written by a binary `COPY`.
+///
+/// ```ignore
+/// impl CopyRow for User {
+///     const COLUMNS: &'static [&'static str] = &["id", "name", "email"];
+///     const TYPES: &'static [Type] = &[Type::UUID, Type::TEXT, Type::TEXT];
+///
+///     fn values(&self) -> Vec<&(dyn ToSql + Sync)> {
+///         vec![&self.id, &self.name, &self.email]
+///     }
+/// }
+/// ```
+pub trait CopyRow {
+    /// Columns written, in the order of the values
+    const COLUMNS: &'static [&'static str];
+    /// Postgres types of the columns
+    const TYPES: &'static [Type];
+
+    /// Values of the row, in the order of the columns
+    fn values(&self) -> Vec<&(dyn ToSql + Sync)>;
+}
+
+/// Bulk load through `COPY ... FROM STDIN BINARY`.
+///
+/// Available on units and transactions, inside a transaction the rows are only visible after
+/// the commit and discarded by the rollback.
+#[async_trait]
+pub trait BulkCopy: PgConnection + Sync {
+    /// Copies the `rows` into the `table`, returning the number of rows written.
+    ///
+    /// The copy is aborted at the first error of the stream.
+    async fn copy_rows<R, S, E>(&self, table: &str, rows: S) -> Result<u64, RepositoryError>
+    where
+        R: CopyRow + Send + Sync,
+        S: Stream<Item = Result<R, E>> + Send,
+        E: Into<RepositoryError> + Send,
+    {
+        debug_assert_eq!(R::COLUMNS.len(), R::TYPES.len());
+
+        let columns = R::COLUMNS
+            .iter()
+            .map(|column| quote_identifier(column))
+            .collect::<Result<Vec<_>, _>>()?
+            .join(", ");
+        let statement = format!(
+            "COPY {} ({columns}) FROM STDIN BINARY",
+            quote_identifier(table)?
+        );
+
+        // NOTE: `GenericClient` does not expose `copy_in`, the copy is made through the client
+        // of the transaction, that is bound to the same session.
+        let sink = self.pg_client().client().copy_in(&statement).await?;
+        let writer = BinaryCopyInWriter::new(sink, R::TYPES);
+        pin_mut!(writer);
+        pin_mut!(rows);
+
+        while let Some(row) = rows.next().await {
+            let row = row.map_err(Into::into)?;
+            writer.as_mut().write(&row.values()).await?;
+        }
+
+        Ok(writer.finish().await?)
+    }
+}
+
+impl<T: PgConnection + Sync> BulkCopy for T {}
@@ -0,0 +1,59 @@
+use futures_util::{stream, TryStreamExt};
+use tokio_postgres::{GenericClient, Row};
+
+use super::{validate_identifier, PgConnection, PgQuery};
+use crate::{RepositoryError, RowStream, TransactionUnit};
+
+/// Server-side cursors, for result sets too large to be sent at once.
+///
+/// Only available on transactions, since the cursor lives until the end of the transaction.
+pub trait TransactionCursor: PgConnection + TransactionUnit + Sync {
+    /// Declares the cursor `name` for the `query` and streams its rows, fetched in batches of
+    /// `batch_size` rows.
+    ///
+    /// The cursor is closed once all rows are fetched.
+    fn cursor_stream<'s>(
+        &'s self,
+        name: &'s str,
+        query: PgQuery<'s>,
+        batch_size: u32,
+    ) -> RowStream<'s, Row> {
+        let client = self.pg_client();
+        let (sql, params) = query;
+
+        let declare = async move {
+            validate_identifier(name)?;
+            let declare = format!("DECLARE \"{name}\" NO SCROLL CURSOR FOR {sql}");
+            client.execute(declare.as_str(), params).await?;
+
+            let fetch = format!("FETCH FORWARD {batch_size} FROM \"{name}\"");
+            let close = format!("CLOSE \"{name}\"");
+            Ok::<_, RepositoryError>(Some((fetch, close)))
+        };
+
+        let batches = move |statements: Option<(String, String)>| async move {
+            let (fetch, close) = match statements {
+                Some(statements) => statements,
+                None => return Ok(None),
+            };
+
+            let rows = client.query(fetch.as_str(), &[]).await?;
+            if rows.is_empty() {
+                client.execute(close.as_str(), &[]).await?;
+                return Ok(None);
+            }
+
+            Ok::<_, RepositoryError>(Some((rows, Some((fetch, close)))))
+        };
+
+        Box::pin(
+            stream::once(declare)
+                .map_ok(move |statements| stream::try_unfold(statements, batches))
+                .try_flatten()
+                .map_ok(|rows| stream::iter(rows.into_iter().map(Ok)))
+                .try_flatten(),
+        )
+    }
+}
+
+impl<T: PgConnection + TransactionUnit + Sync> TransactionCursor for T {}
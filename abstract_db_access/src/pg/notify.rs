@@ -0,0 +1,144 @@
+use std::{
+    collections::VecDeque,
+    future::{poll_fn, Future},
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use async_trait::async_trait;
+use futures_util::Stream;
+use tokio_postgres::{
+    tls::MakeTlsConnect, AsyncMessage, Client, Config, Connection, GenericClient, Notification,
+    Socket,
+};
+
+use super::{validate_identifier, PgConnection};
+use crate::{RepositoryError, TransactionUnit};
+
+/// Notifications sent from a transaction.
+///
+/// Only available on transactions, the server delivers the notifications to the listeners when
+/// the transaction commits and discards them on rollback.
+#[async_trait]
+pub trait TransactionNotify: PgConnection + TransactionUnit + Sync {
+    /// Queues a notification with the `payload` on the `channel`.
+    async fn notify(&self, channel: &str, payload: &str) -> Result<(), RepositoryError> {
+        validate_identifier(channel)?;
+        self.pg_client()
+            .execute("SELECT pg_notify($1, $2)", &[&channel, &payload])
+            .await?;
+        Ok(())
+    }
+}
+
+impl<T: PgConnection + TransactionUnit + Sync> TransactionNotify for T {}
+
+type PollMessage =
+    dyn FnMut(&mut Context<'_>) -> Poll<Option<Result<AsyncMessage, tokio_postgres::Error>>> + Send;
+
+/// Stream of the notifications received on the listened channels.
+///
+/// Requires a dedicated connection, since the messages sent asynchronously by the server are
+/// only visible to whom drives the connection. Pooled connections, like the deadpool ones, are
+/// driven by the pool and can not be used to listen, even when detached from it.
+pub struct Listener {
+    client: Client,
+    poll_message: Box<PollMessage>,
+    pending: VecDeque<Notification>,
+}
+
+impl Listener {
+    /// Opens a dedicated connection to listen for notifications.
+    pub async fn connect<T>(config: &Config, tls: T) -> Result<Self, RepositoryError>
+    where
+        T: MakeTlsConnect<Socket>,
+        T::Stream: Send + 'static,
+    {
+        let (client, connection) = config.connect(tls).await?;
+        Ok(Self::new(client, connection))
+    }
+
+    /// Listens through the `connection`, which must not be driven elsewhere.
+    pub fn new<S, T>(client: Client, mut connection: Connection<S, T>) -> Self
+    where
+        S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin + Send + 'static,
+        T: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin + Send + 'static,
+    {
+        Self {
+            client,
+            poll_message: Box::new(move |cx| connection.poll_message(cx)),
+            pending: VecDeque::new(),
+        }
+    }
+
+    /// Starts listening for notifications on the `channel`.
+    pub async fn listen(&mut self, channel: &str) -> Result<(), RepositoryError> {
+        validate_identifier(channel)?;
+        self.batch_execute(&format!("LISTEN \"{channel}\"")).await
+    }
+
+    /// Stops listening for notifications on the `channel`.
+    pub async fn unlisten(&mut self, channel: &str) -> Result<(), RepositoryError> {
+        validate_identifier(channel)?;
+        self.batch_execute(&format!("UNLISTEN \"{channel}\"")).await
+    }
+
+    /// Executes the `query` driving the connection until its response arrives.
+    ///
+    /// Notifications received meanwhile are kept to be yielded by the stream.
+    async fn batch_execute(&mut self, query: &str) -> Result<(), RepositoryError> {
+        let Self {
+            client,
+            poll_message,
+            pending,
+        } = self;
+        let mut request = Box::pin(client.batch_execute(query));
+
+        poll_fn(|cx| {
+            if let Poll::Ready(res) = request.as_mut().poll(cx) {
+                return Poll::Ready(res.map_err(Into::into));
+            }
+
+            loop {
+                match poll_message(cx) {
+                    Poll::Ready(Some(Ok(AsyncMessage::Notification(notification)))) => {
+                        pending.push_back(notification)
+                    }
+                    Poll::Ready(Some(Ok(_))) => {}
+                    Poll::Ready(Some(Err(err))) => return Poll::Ready(Err(err.into())),
+                    Poll::Ready(None) => {
+                        return Poll::Ready(Err(RepositoryError::Unknown(
+                            "listener connection closed".into(),
+                        )))
+                    }
+                    Poll::Pending => return Poll::Pending,
+                }
+            }
+        })
+        .await
+    }
+}
+
+impl Stream for Listener {
+    type Item = Result<Notification, RepositoryError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+
+        if let Some(notification) = this.pending.pop_front() {
+            return Poll::Ready(Some(Ok(notification)));
+        }
+
+        loop {
+            match (this.poll_message)(cx) {
+                Poll::Ready(Some(Ok(AsyncMessage::Notification(notification)))) => {
+                    return Poll::Ready(Some(Ok(notification)))
+                }
+                Poll::Ready(Some(Ok(_))) => {}
+                Poll::Ready(Some(Err(err))) => return Poll::Ready(Some(Err(err.into()))),
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
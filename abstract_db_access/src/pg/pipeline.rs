@@ -0,0 +1,207 @@
+use std::{
+    future::{poll_fn, Future},
+    pin::Pin,
+    task::Poll,
+};
+
+use futures_util::future::try_join_all;
+use tokio_postgres::{types::ToSql, GenericClient, Row};
+
+use super::PgConnection;
+use crate::RepositoryError;
+
+/// Batches of statements sent to the server without waiting for each other.
+pub trait Pipelined: PgConnection {
+    /// Starts a batch of statements executed on this unit or transaction.
+    ///
+    /// Borrows the unit mutably, so no other statement is mixed into the transaction of the
+    /// batch.
+    fn pipeline(&mut self) -> Pipeline<'_, Self::Client> {
+        Pipeline {
+            client: self.pg_client(),
+            atomic: !self.is_transaction(),
+            statements: Vec::new(),
+        }
+    }
+}
+
+impl<T: PgConnection> Pipelined for T {}
+
+/// Result of a statement of the pipeline
+#[derive(Debug)]
+pub enum PipelineOutput {
+    /// Rows returned by a queued `query`
+    Rows(Vec<Row>),
+    /// Number of rows affected by a queued `execute`
+    Affected(u64),
+}
+
+impl PipelineOutput {
+    pub fn into_rows(self) -> Option<Vec<Row>> {
+        match self {
+            Self::Rows(rows) => Some(rows),
+            Self::Affected(_) => None,
+        }
+    }
+
+    pub fn affected(&self) -> Option<u64> {
+        match self {
+            Self::Rows(_) => None,
+            Self::Affected(affected) => Some(*affected),
+        }
+    }
+}
+
+enum StatementKind {
+    Query,
+    Execute,
+}
+
+struct Queued<'p> {
+    kind: StatementKind,
+    sql: &'p str,
+    params: &'p [&'p (dyn ToSql + Sync)],
+}
+
+type PendingOutput<'p> =
+    Pin<Box<dyn Future<Output = Result<PipelineOutput, tokio_postgres::Error>> + Send + 'p>>;
+
+/// Statements queued to be pipelined.
+///
+/// All statements are prepared in a single round trip, and then executed in a second one, in
+/// the order they were queued.
+///
+/// Rollback semantics:
+/// - on a unit, the statements run inside a transaction of their own, committed only if all of
+///   them succeed. `BEGIN` and `COMMIT` are pipelined with the statements: the server turns the
+///   `COMMIT` of a transaction aborted by a failed statement into a rollback, and the whole batch
+///   is queued on the connection at once, so dropping the future never leaves the connection
+///   inside the transaction.
+/// - on a transaction, a failed statement aborts the transaction, failing the statements after
+///   it, and the transaction must be rolled back.
+pub struct Pipeline<'p, C: GenericClient> {
+    client: &'p C,
+    atomic: bool,
+    statements: Vec<Queued<'p>>,
+}
+
+impl<'p, C: GenericClient + Sync> Pipeline<'p, C> {
+    /// Queues a statement returning rows.
+    pub fn query(mut self, sql: &'p str, params: &'p [&'p (dyn ToSql + Sync)]) -> Self {
+        self.statements.push(Queued {
+            kind: StatementKind::Query,
+            sql,
+            params,
+        });
+        self
+    }
+
+    /// Queues a statement returning the number of affected rows.
+    pub fn execute(mut self, sql: &'p str, params: &'p [&'p (dyn ToSql + Sync)]) -> Self {
+        self.statements.push(Queued {
+            kind: StatementKind::Execute,
+            sql,
+            params,
+        });
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.statements.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.statements.is_empty()
+    }
+
+    /// Sends the queued statements, returning their outputs in the order they were queued.
+    ///
+    /// Fails with the error of the first statement that failed.
+    pub async fn run(self) -> Result<Vec<PipelineOutput>, RepositoryError> {
+        if self.statements.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let client = self.client;
+        let prepared = try_join_all(
+            self.statements
+                .iter()
+                .map(|statement| client.prepare(statement.sql)),
+        )
+        .await?;
+
+        let mut pending: Vec<PendingOutput<'_>> = Vec::with_capacity(prepared.len() + 2);
+        if self.atomic {
+            pending.push(simple_statement(client, "BEGIN"));
+        }
+        for (queued, statement) in self.statements.iter().zip(&prepared) {
+            pending.push(match queued.kind {
+                StatementKind::Query => Box::pin(async move {
+                    let rows = client.query(statement, queued.params).await?;
+                    Ok(PipelineOutput::Rows(rows))
+                }),
+                StatementKind::Execute => Box::pin(async move {
+                    let affected = client.execute(statement, queued.params).await?;
+                    Ok(PipelineOutput::Affected(affected))
+                }),
+            });
+        }
+        if self.atomic {
+            pending.push(simple_statement(client, "COMMIT"));
+        }
+
+        let mut outputs = join_in_order(pending).await;
+
+        if self.atomic {
+            let commit = outputs.pop().expect("queued commit");
+            let begin = outputs.remove(0);
+            begin?;
+            // NOTE: the error of the failed statement is returned first, the commit then only
+            // reports that the transaction was rolled back
+            let outputs = outputs.into_iter().collect::<Result<_, _>>()?;
+            commit?;
+            return Ok(outputs);
+        }
+
+        Ok(outputs.into_iter().collect::<Result<_, _>>()?)
+    }
+}
+
+/// Statement without parameters nor output, sent through the simple query protocol
+fn simple_statement<'p, C: GenericClient + Sync>(client: &'p C, sql: &'p str) -> PendingOutput<'p> {
+    Box::pin(async move {
+        client.client().batch_execute(sql).await?;
+        Ok(PipelineOutput::Affected(0))
+    })
+}
+
+/// Drives all the futures to completion, polling them in order.
+///
+/// The order matters, each statement is sent to the server on the first poll of its future.
+async fn join_in_order<T>(
+    mut pending: Vec<Pin<Box<dyn Future<Output = T> + Send + '_>>>,
+) -> Vec<T> {
+    let mut outputs: Vec<Option<T>> = pending.iter().map(|_| None).collect();
+
+    poll_fn(|cx| {
+        let mut done = true;
+        for (fut, output) in pending.iter_mut().zip(outputs.iter_mut()) {
+            if output.is_some() {
+                continue;
+            }
+            match fut.as_mut().poll(cx) {
+                Poll::Ready(res) => *output = Some(res),
+                Poll::Pending => done = false,
+            }
+        }
+
+        if done {
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    })
+    .await;
+
+    outputs.into_iter().flatten().collect()
+}
@@ -0,0 +1,281 @@
+use async_trait::async_trait;
+use tokio_postgres::{types::ToSql, GenericClient};
+
+use super::{quote_identifier, FromRow, PgConnection};
+use crate::{
+    repository::{chunk_size, find_many_statement},
+    Bind, DbAccess, Entity, EntityDriver, InTransaction, Repository, RepositoryError,
+};
+
+/// Limit of parameters in a statement, tokio-postgres sending their count as an `i16`
+const MAX_PARAMETERS: usize = i16::MAX as usize;
+
+/// Values of an [`Entity`] bound to the Postgres statements.
+///
+/// ```ignore
+/// impl PgEntity for User {
+///     fn key_values(key: &Uuid) -> Vec<&(dyn ToSql + Sync)> {
+///         vec![key]
+///     }
+///
+///     fn values(&self) -> Vec<&(dyn ToSql + Sync)> {
+///         vec![&self.id, &self.name, &self.email]
+///     }
+/// }
+/// ```
+pub trait PgEntity: Entity + FromRow {
+    /// Values of the `key`, in the order of the key columns
+    fn key_values(key: &Self::Key) -> Vec<&(dyn ToSql + Sync)>;
+
+    /// Values of the entity, in the order of the columns
+    fn values(&self) -> Vec<&(dyn ToSql + Sync)>;
+}
+
+/// Parameters of the statement, in the order of the `binds`
+fn params<'a, E: PgEntity>(binds: &'a [Bind<'a, E>]) -> Vec<&'a (dyn ToSql + Sync)> {
+    binds
+        .iter()
+        .flat_map(|bind| match bind {
+            Bind::Columns(entity) => entity.values(),
+            Bind::Key(key) => E::key_values(key),
+        })
+        .collect()
+}
+
+#[async_trait]
+impl<E, T> EntityDriver<E> for T
+where
+    E: PgEntity,
+    T: PgConnection + DbAccess + Send + Sync,
+{
+    const MAX_PARAMETERS: usize = MAX_PARAMETERS;
+
+    fn placeholder(idx: usize) -> String {
+        format!("${idx}")
+    }
+
+    fn quote_identifier(name: &str) -> Result<String, RepositoryError> {
+        quote_identifier(name)
+    }
+
+    fn describe_key(entity: &E) -> String {
+        let values = entity.values();
+        E::KEY
+            .iter()
+            .filter_map(|key| E::COLUMNS.iter().position(|column| column == key))
+            .map(|idx| format!("{:?}", values[idx]))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    async fn execute_entity(
+        &mut self,
+        sql: &str,
+        binds: &[Bind<'_, E>],
+    ) -> Result<u64, RepositoryError> {
+        Ok(self.pg_client().execute(sql, &params(binds)).await?)
+    }
+
+    async fn query_entities(
+        &self,
+        sql: &str,
+        binds: &[Bind<'_, E>],
+    ) -> Result<Vec<E>, RepositoryError> {
+        let rows = self.pg_client().query(sql, &params(binds)).await?;
+        rows.iter().map(E::from_row).collect()
+    }
+
+    async fn query_i64(
+        &self,
+        sql: &str,
+        binds: &[Bind<'_, E>],
+    ) -> Result<Option<i64>, RepositoryError> {
+        let row = self.pg_client().query_opt(sql, &params(binds)).await?;
+        Ok(row.map(|row| row.try_get(0)).transpose()?)
+    }
+}
+
+/// Behaviour of a locking read when the rows are locked by another transaction
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RowLock {
+    /// Waits for the rows to be unlocked
+    #[default]
+    Wait,
+    /// Fails with [`RepositoryError::LockNotAvailable`]
+    NoWait,
+    /// Skips the locked rows
+    SkipLocked,
+}
+
+impl RowLock {
+    /// Locking clause of the `SELECT`
+    fn clause(self) -> &'static str {
+        match self {
+            RowLock::Wait => "FOR UPDATE",
+            RowLock::NoWait => "FOR UPDATE NOWAIT",
+            RowLock::SkipLocked => "FOR UPDATE SKIP LOCKED",
+        }
+    }
+}
+
+/// Finds locking the rows through `SELECT ... FOR UPDATE`.
+///
+/// Only available on transactions, since the rows are locked until the end of the transaction.
+#[async_trait]
+pub trait TransactionRowLock<E: PgEntity>: Repository<E> + PgConnection + InTransaction {
+    /// Finds the entity of the `key`, locking its row.
+    async fn find_by_id_for_update(
+        &self,
+        key: &E::Key,
+        lock: RowLock,
+    ) -> Result<Option<E>, RepositoryError> {
+        let statement = format!("{} {}", find_many_statement::<E, Self>(1)?, lock.clause());
+        let row = self
+            .pg_client()
+            .query_opt(&statement, &E::key_values(key))
+            .await?;
+        row.as_ref().map(E::from_row).transpose()
+    }
+
+    /// Finds the entities of the `keys`, locking their rows.
+    async fn find_many_for_update(
+        &self,
+        keys: &[E::Key],
+        lock: RowLock,
+    ) -> Result<Vec<E>, RepositoryError> {
+        let mut found = Vec::new();
+        let mut keys = keys;
+        for (count, statement) in find_many_for_update_statements::<E, Self>(keys.len(), lock)? {
+            let (chunk, rest) = keys.split_at(count);
+            keys = rest;
+
+            let params: Vec<_> = chunk.iter().flat_map(E::key_values).collect();
+            let rows = self.pg_client().query(&statement, &params).await?;
+            for row in &rows {
+                found.push(E::from_row(row)?);
+            }
+        }
+
+        Ok(found)
+    }
+}
+
+/// Locking statements finding `count` keys, chunked as [`Repository::find_many`] to fit the
+/// parameters of a statement, each along with the number of keys it binds
+fn find_many_for_update_statements<E, D>(
+    count: usize,
+    lock: RowLock,
+) -> Result<Vec<(usize, String)>, RepositoryError>
+where
+    E: PgEntity,
+    D: EntityDriver<E> + ?Sized,
+{
+    let size = chunk_size(D::MAX_PARAMETERS, E::KEY);
+    (0..count)
+        .step_by(size)
+        .map(|start| {
+            let len = size.min(count - start);
+            let statement = format!("{} {}", find_many_statement::<E, D>(len)?, lock.clause());
+            Ok((len, statement))
+        })
+        .collect()
+}
+
+impl<E, T> TransactionRowLock<E> for T
+where
+    E: PgEntity,
+    T: Repository<E> + PgConnection + InTransaction,
+{
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio_postgres::Client;
+
+    use super::*;
+    use crate::pg::Row;
+
+    struct UserTag;
+
+    impl Entity for UserTag {
+        type Key = (i64, String);
+
+        const TABLE: &'static str = "user_tag";
+        const KEY: &'static [&'static str] = &["user_id", "tag"];
+        const COLUMNS: &'static [&'static str] = &["user_id", "tag"];
+    }
+
+    impl FromRow for UserTag {
+        fn from_row(_row: &Row) -> Result<Self, RepositoryError> {
+            unreachable!()
+        }
+    }
+
+    impl PgEntity for UserTag {
+        fn key_values(key: &Self::Key) -> Vec<&(dyn ToSql + Sync)> {
+            vec![&key.0, &key.1]
+        }
+
+        fn values(&self) -> Vec<&(dyn ToSql + Sync)> {
+            unreachable!()
+        }
+    }
+
+    /// Connection building the statements, never running them
+    struct Connection;
+
+    impl DbAccess for Connection {
+        type Connection = Client;
+    }
+
+    impl PgConnection for Connection {
+        type Client = Client;
+
+        fn pg_client(&self) -> &Self::Client {
+            unreachable!()
+        }
+
+        fn is_transaction(&self) -> bool {
+            true
+        }
+    }
+
+    fn statements(count: usize) -> Vec<(usize, String)> {
+        find_many_for_update_statements::<UserTag, Connection>(count, RowLock::NoWait).unwrap()
+    }
+
+    #[test]
+    fn locks_the_keys_fitting_in_a_statement_at_once() {
+        let chunk = MAX_PARAMETERS / UserTag::KEY.len();
+
+        let statements = statements(chunk);
+
+        assert_eq!(statements.len(), 1);
+        let (count, statement) = &statements[0];
+        assert_eq!(*count, chunk);
+        assert!(statement.contains(&format!(
+            "${}, ${})) FOR UPDATE NOWAIT",
+            2 * chunk - 1,
+            2 * chunk
+        )));
+    }
+
+    #[test]
+    fn chunks_the_keys_past_the_parameters_limit() {
+        let chunk = MAX_PARAMETERS / UserTag::KEY.len();
+
+        let statements = statements(chunk + 1);
+
+        let counts: Vec<_> = statements.iter().map(|(count, _)| *count).collect();
+        assert_eq!(counts, [chunk, 1]);
+        assert!(!statements[0].1.contains(&format!("${}", 2 * chunk + 1)));
+        assert!(statements[1]
+            .1
+            .ends_with(r#"WHERE ("user_id", "tag") IN (($1, $2)) FOR UPDATE NOWAIT"#));
+    }
+
+    #[test]
+    fn builds_no_statement_without_keys() {
+        assert!(statements(0).is_empty());
+    }
+}
@@ -0,0 +1,38 @@
+pub use tokio_postgres::Row;
+
+use crate::RepositoryError;
+
+#[cfg(feature = "macros")]
+pub use abstract_db_access_macros::FromRow;
+
+/// Value built from a row returned by a query, the counterpart of sqlx's `FromRow`.
+///
+/// Unlike `Row::get`, a missing column or a type mismatch is returned as an error instead of
+/// panicking. With the `macros` feature it can be derived for structs with named fields:
+///
+/// ```ignore
+/// #[derive(FromRow)]
+/// struct User {
+///     id: Uuid,
+///     // read from the `user_name` column
+///     #[row(rename = "user_name")]
+///     name: String,
+///     // NULL is read as None
+///     email: Option<String>,
+///     // `Default::default()` when the column is not selected
+///     #[row(default)]
+///     roles: Vec<String>,
+///     // built from the columns of the same row
+///     #[row(flatten)]
+///     address: Address,
+/// }
+/// ```
+pub trait FromRow: Sized {
+    fn from_row(row: &Row) -> Result<Self, RepositoryError>;
+}
+
+/// Indicates if the `row` has the `column`, used by the derived [`FromRow`] for default fields.
+#[doc(hidden)]
+pub fn has_column(row: &Row, column: &str) -> bool {
+    row.columns().iter().any(|col| col.name() == column)
+}
@@ -0,0 +1,133 @@
+use postgres::GenericClient;
+
+use super::{
+    blocking::{DbUnit, InTransaction, SavePoint, TransactionUnit, Transactor},
+    AutoCommit, DbAccess, RepositoryError, TransactionOptions, TransactionState,
+};
+
+pub type PgUnit = postgres::Client;
+
+pub struct PgTrxUnit<'t> {
+    // NOTE: not possible to disambiguate `postgres::Client::transaction` from
+    // `DbUnit::transaction` on the unit, so the transaction client type is not wrapped either
+    pub client: postgres::Transaction<'t>,
+    pub state: TransactionState,
+}
+
+/// Unit or transaction running the statements of the repositories.
+///
+/// The synchronous client needs to be borrowed mutably to run a statement.
+pub trait PgConnection {
+    type Client: GenericClient;
+
+    fn pg_client(&mut self) -> &mut Self::Client;
+
+    /// Indicates if the statements run inside a transaction
+    fn is_transaction(&self) -> bool;
+}
+
+impl PgConnection for PgUnit {
+    type Client = postgres::Client;
+
+    fn pg_client(&mut self) -> &mut Self::Client {
+        self
+    }
+
+    fn is_transaction(&self) -> bool {
+        false
+    }
+}
+
+impl DbAccess for PgUnit {
+    type Connection = postgres::Client;
+}
+
+impl Transactor for PgUnit {
+    type Transaction<'t> = PgTrxUnit<'t>;
+}
+
+impl AutoCommit for PgUnit {}
+
+impl DbUnit for PgUnit {
+    fn transaction(&mut self) -> Result<Self::Transaction<'_>, RepositoryError> {
+        let client = postgres::Client::transaction(self)?;
+        let state = TransactionState::from_open_transaction(0);
+        Ok(PgTrxUnit { client, state })
+    }
+
+    fn transaction_with(
+        &mut self,
+        options: TransactionOptions,
+    ) -> Result<Self::Transaction<'_>, RepositoryError> {
+        let state = TransactionState::from_options(0, &options);
+        let mut client = postgres::Client::transaction(self)?;
+
+        let settings = options.settings();
+        if !settings.is_empty() {
+            let (names, values): (Vec<&str>, Vec<&str>) = settings
+                .iter()
+                .map(|(name, value)| (name.as_str(), value.as_str()))
+                .unzip();
+
+            client.execute(
+                "SELECT set_config(name, value, true) FROM unnest($1::text[], $2::text[]) AS s(name, value)",
+                &[&names, &values],
+            )?;
+        }
+
+        Ok(PgTrxUnit { client, state })
+    }
+}
+
+impl<'t> PgConnection for PgTrxUnit<'t> {
+    type Client = postgres::Transaction<'t>;
+
+    fn pg_client(&mut self) -> &mut Self::Client {
+        &mut self.client
+    }
+
+    fn is_transaction(&self) -> bool {
+        true
+    }
+}
+
+impl<'t> DbAccess for PgTrxUnit<'t> {
+    type Connection = postgres::Client;
+}
+
+impl<'t> Transactor for PgTrxUnit<'t> {
+    type Transaction<'trx> = PgTrxUnit<'trx>;
+}
+
+impl<'t> InTransaction for PgTrxUnit<'t> {}
+
+impl<'t> TransactionUnit for PgTrxUnit<'t> {
+    fn commit(self) -> Result<(), RepositoryError> {
+        if self.state.is_expired() {
+            self.client.rollback()?;
+            return Err(RepositoryError::Timeout);
+        }
+
+        Ok(self.client.commit()?)
+    }
+
+    fn rollback(self) -> Result<(), RepositoryError> {
+        Ok(self.client.rollback()?)
+    }
+}
+
+impl<'t> SavePoint for PgTrxUnit<'t> {
+    fn save_point(&mut self, name: &str) -> Result<Self::Transaction<'_>, RepositoryError> {
+        if self.state.is_expired() {
+            return Err(RepositoryError::Timeout);
+        }
+
+        let state = self.state.nested();
+        let client = self.client.savepoint(name)?;
+        Ok(PgTrxUnit { client, state })
+    }
+
+    fn depth(&self) -> u32 {
+        self.state.depth()
+    }
+}
@@ -1,9 +1,15 @@
 use async_trait::async_trait;
 
 use super::{
-    DbAccess, DbUnit, RepositoryError, SavePoint, TransactionState, TransactionUnit, Transactor,
+    pg, AutoCommit, DbAccess, DbUnit, InTransaction, QueryStream, RepositoryError, RowStream,
+    SavePoint, TransactionOptions, TransactionState, TransactionUnit, Transactor,
 };
 
+#[cfg(feature = "native_async")]
+use super::native;
+
+pub mod tenant;
+
 pub type PgUnit = deadpool_postgres::Client;
 
 pub struct PgTrxUnit<'t> {
@@ -11,6 +17,28 @@ pub struct PgTrxUnit<'t> {
     // so the transaction client type is not wrapped
     pub client: tokio_postgres::Transaction<'t>,
     pub state: TransactionState,
+    deadline: pg::Deadline,
+    /// Cancels the running query at the deadline, held by the transaction but not its save points
+    _watchdog: Option<pg::Watchdog>,
+}
+
+impl<'t> PgTrxUnit<'t> {
+    /// Deadline bounding the operations of this transaction
+    pub fn deadline(&self) -> pg::Deadline {
+        self.deadline.clone()
+    }
+}
+
+impl pg::PgConnection for PgUnit {
+    type Client = tokio_postgres::Client;
+
+    fn pg_client(&self) -> &Self::Client {
+        self
+    }
+
+    fn is_transaction(&self) -> bool {
+        false
+    }
 }
 
 impl DbAccess for PgUnit {
@@ -21,12 +49,59 @@ impl Transactor for PgUnit {
     type Transaction<'t> = PgTrxUnit<'t>;
 }
 
+impl AutoCommit for PgUnit {}
+
+impl QueryStream for PgUnit {
+    type Row = tokio_postgres::Row;
+    type Query<'q> = pg::PgQuery<'q>;
+
+    fn query_stream<'s, 'q: 's>(&'s mut self, query: Self::Query<'q>) -> RowStream<'s, Self::Row> {
+        pg::query_stream(&***self, query)
+    }
+}
+
 #[async_trait]
-impl<'t> DbUnit for PgUnit {
+impl DbUnit for PgUnit {
     async fn transaction<'s>(&'s mut self) -> Result<Self::Transaction<'s>, RepositoryError> {
-        let client = tokio_postgres::Client::transaction(self).await?;
-        let state = TransactionState::from_open_transaction(0);
-        Ok(Self::Transaction { client, state })
+        begin(self).await
+    }
+
+    async fn transaction_with<'s>(
+        &'s mut self,
+        options: TransactionOptions,
+    ) -> Result<Self::Transaction<'s>, RepositoryError> {
+        begin_with(self, options).await
+    }
+}
+
+#[cfg(feature = "native_async")]
+impl native::Transactor for PgUnit {
+    type Transaction<'t> = PgTrxUnit<'t>;
+}
+
+#[cfg(feature = "native_async")]
+impl native::DbUnit for PgUnit {
+    async fn transaction<'s>(&'s mut self) -> Result<PgTrxUnit<'s>, RepositoryError> {
+        begin(self).await
+    }
+
+    async fn transaction_with<'s>(
+        &'s mut self,
+        options: TransactionOptions,
+    ) -> Result<PgTrxUnit<'s>, RepositoryError> {
+        begin_with(self, options).await
+    }
+}
+
+impl<'t> pg::PgConnection for PgTrxUnit<'t> {
+    type Client = tokio_postgres::Transaction<'t>;
+
+    fn pg_client(&self) -> &Self::Client {
+        &self.client
+    }
+
+    fn is_transaction(&self) -> bool {
+        true
     }
 }
 
@@ -38,16 +113,25 @@ impl<'t> Transactor for PgTrxUnit<'t> {
     type Transaction<'trx> = PgTrxUnit<'trx>;
 }
 
+impl<'t> InTransaction for PgTrxUnit<'t> {}
+
+impl<'t> QueryStream for PgTrxUnit<'t> {
+    type Row = tokio_postgres::Row;
+    type Query<'q> = pg::PgQuery<'q>;
+
+    fn query_stream<'s, 'q: 's>(&'s mut self, query: Self::Query<'q>) -> RowStream<'s, Self::Row> {
+        pg::query_stream(&self.client, query)
+    }
+}
+
 #[async_trait]
 impl<'t> TransactionUnit for PgTrxUnit<'t> {
     async fn commit(self) -> Result<(), RepositoryError> {
-        self.client.commit().await?;
-        Ok(())
+        commit(self).await
     }
 
     async fn rollback(self) -> Result<(), RepositoryError> {
-        self.client.rollback().await?;
-        Ok(())
+        rollback(self).await
     }
 }
 
@@ -57,12 +141,123 @@ impl<'t> SavePoint for PgTrxUnit<'t> {
         &'s mut self,
         name: &str,
     ) -> Result<Self::Transaction<'s>, RepositoryError> {
-        let state = TransactionState::from_open_transaction(self.depth() + 1);
-        let client = self.client.savepoint(name).await?;
-        Ok(Self::Transaction { client, state })
+        save_point(self, name).await
+    }
+
+    fn depth(&self) -> u32 {
+        self.state.depth
+    }
+}
+
+#[cfg(feature = "native_async")]
+impl<'t> native::Transactor for PgTrxUnit<'t> {
+    type Transaction<'trx> = PgTrxUnit<'trx>;
+}
+
+#[cfg(feature = "native_async")]
+impl<'t> native::TransactionUnit for PgTrxUnit<'t> {
+    async fn commit(self) -> Result<(), RepositoryError> {
+        commit(self).await
+    }
+
+    async fn rollback(self) -> Result<(), RepositoryError> {
+        rollback(self).await
+    }
+}
+
+#[cfg(feature = "native_async")]
+impl<'t> native::SavePoint for PgTrxUnit<'t> {
+    async fn save_point<'s>(&'s mut self, name: &str) -> Result<PgTrxUnit<'s>, RepositoryError> {
+        save_point(self, name).await
     }
 
     fn depth(&self) -> u32 {
         self.state.depth
     }
 }
+
+async fn begin(unit: &mut PgUnit) -> Result<PgTrxUnit<'_>, RepositoryError> {
+    let deadline = pg::Deadline::new(unit.cancel_token(), Default::default(), None);
+    let client = tokio_postgres::Client::transaction(unit).await?;
+    let state = TransactionState::from_open_transaction(0);
+    Ok(PgTrxUnit {
+        client,
+        state,
+        deadline,
+        _watchdog: None,
+    })
+}
+
+async fn begin_with(
+    unit: &mut PgUnit,
+    options: TransactionOptions,
+) -> Result<PgTrxUnit<'_>, RepositoryError> {
+    let state = TransactionState::from_options(0, &options);
+    let deadline = deadline_of(unit, &state).await?;
+    let client = deadline.run(pg::begin(unit, options.settings())).await?;
+    Ok(PgTrxUnit {
+        client,
+        state,
+        _watchdog: deadline.watch(),
+        deadline,
+    })
+}
+
+/// Deadline of a transaction of the `unit`.
+///
+/// The queries are cancelled from another connection of the pool, connected with the TLS
+/// configuration of the pool, which a cancel request sent by the unit has no access to.
+async fn deadline_of(
+    unit: &PgUnit,
+    state: &TransactionState,
+) -> Result<pg::Deadline, RepositoryError> {
+    let pool = match (state.deadline(), deadpool_postgres::Object::pool(unit)) {
+        (Some(_), Some(pool)) => pool,
+        _ => {
+            return Ok(pg::Deadline::new(
+                unit.cancel_token(),
+                Default::default(),
+                state.deadline(),
+            ))
+        }
+    };
+
+    let pid = unit.query_one("SELECT pg_backend_pid()", &[]).await?.get(0);
+    Ok(pg::Deadline::with_pool(
+        pool,
+        pid,
+        unit.cancel_token(),
+        state.deadline(),
+    ))
+}
+
+async fn commit(trx: PgTrxUnit<'_>) -> Result<(), RepositoryError> {
+    if trx.state.is_expired() {
+        // dropping the transaction sends the rollback without waiting for the server
+        drop(trx.client);
+        return Err(RepositoryError::Timeout);
+    }
+
+    let deadline = trx.deadline();
+    deadline.run(trx.client.commit()).await
+}
+
+async fn rollback(trx: PgTrxUnit<'_>) -> Result<(), RepositoryError> {
+    let deadline = trx.deadline();
+    deadline.run(trx.client.rollback()).await
+}
+
+async fn save_point<'s>(
+    trx: &'s mut PgTrxUnit<'_>,
+    name: &str,
+) -> Result<PgTrxUnit<'s>, RepositoryError> {
+    let state = trx.state.nested();
+    let deadline = trx.deadline();
+    let client = deadline.run(trx.client.savepoint(name)).await?;
+    Ok(PgTrxUnit {
+        client,
+        state,
+        deadline,
+        _watchdog: None,
+    })
+}
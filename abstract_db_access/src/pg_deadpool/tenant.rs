@@ -0,0 +1,208 @@
+use std::ops::{Deref, DerefMut};
+
+use async_trait::async_trait;
+use futures_util::FutureExt;
+
+use super::{PgTrxUnit, PgUnit};
+#[cfg(feature = "native_async")]
+use crate::native;
+use crate::{pg, AutoCommit, DbAccess, DbUnit, RepositoryError, TransactionOptions, Transactor};
+
+/// Statement resetting the `search_path` of a tenant connection.
+///
+/// Pools serving tenant units can recycle with
+/// `RecyclingMethod::Custom(RESET_SEARCH_PATH.into())`, so a connection whose reset failed is
+/// discarded instead of being handed to the next borrower.
+pub const RESET_SEARCH_PATH: &str = "RESET search_path";
+
+/// Name of a tenant schema.
+///
+/// Only lowercase unquoted identifiers are accepted: starting with a letter or underscore and
+/// followed by letters, digits, underscores or dollar signs. The `pg_` prefix is reserved to
+/// the system schemas.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SchemaName(String);
+
+impl SchemaName {
+    pub fn new(name: impl Into<String>) -> Result<Self, RepositoryError> {
+        let name = name.into();
+
+        pg::validate_identifier(&name)?;
+        if name.starts_with("pg_") {
+            return Err(RepositoryError::InvalidIdentifier(name));
+        }
+
+        Ok(Self(name))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+/// Unit bound to the schema of a tenant.
+///
+/// The `search_path` of the connection is set to the tenant schema on acquisition and reset
+/// on [`TenantUnit::release`]. A unit dropped without being released queues the reset before
+/// going back to the pool, and is only detached from the pool when the reset can't be sent.
+pub struct TenantUnit {
+    unit: Option<PgUnit>,
+    schema: SchemaName,
+}
+
+impl TenantUnit {
+    /// Acquires a connection from the `pool` bound to the tenant `schema`.
+    pub async fn acquire(
+        pool: &deadpool_postgres::Pool,
+        schema: SchemaName,
+    ) -> Result<Self, RepositoryError> {
+        let unit = pool.get().await?;
+        Self::new(unit, schema).await
+    }
+
+    /// Binds the `unit` to the tenant `schema`.
+    pub async fn new(unit: PgUnit, schema: SchemaName) -> Result<Self, RepositoryError> {
+        // NOTE: the schema name is already validated, the quotes keep it as a single identifier
+        let search_path = format!("\"{}\"", schema.as_str());
+        unit.execute(
+            "SELECT set_config('search_path', $1, false)",
+            &[&search_path],
+        )
+        .await?;
+
+        Ok(Self {
+            unit: Some(unit),
+            schema,
+        })
+    }
+
+    pub fn schema(&self) -> &SchemaName {
+        &self.schema
+    }
+
+    /// Resets the `search_path` and hands the connection back to the pool.
+    ///
+    /// If the reset fails the connection is detached from the pool.
+    pub async fn release(mut self) -> Result<(), RepositoryError> {
+        let unit = self.unit.take().expect("tenant unit already released");
+
+        if let Err(err) = unit.batch_execute(RESET_SEARCH_PATH).await {
+            drop(deadpool_postgres::Object::take(unit));
+            return Err(err.into());
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for TenantUnit {
+    fn drop(&mut self) {
+        if let Some(unit) = self.unit.take() {
+            // NOTE: the reset is sent on the first poll, without waiting for the server response,
+            // so it runs ahead of the statements of the next borrower of the connection.
+            let reset = unit.batch_execute(RESET_SEARCH_PATH).now_or_never();
+            if let Some(Err(_)) = reset {
+                drop(deadpool_postgres::Object::take(unit));
+            }
+        }
+    }
+}
+
+impl Deref for TenantUnit {
+    type Target = PgUnit;
+
+    fn deref(&self) -> &Self::Target {
+        self.unit.as_ref().expect("tenant unit already released")
+    }
+}
+
+impl DerefMut for TenantUnit {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.unit.as_mut().expect("tenant unit already released")
+    }
+}
+
+impl pg::PgConnection for TenantUnit {
+    type Client = tokio_postgres::Client;
+
+    fn pg_client(&self) -> &Self::Client {
+        self
+    }
+
+    fn is_transaction(&self) -> bool {
+        false
+    }
+}
+
+impl DbAccess for TenantUnit {
+    type Connection = deadpool_postgres::Client;
+}
+
+impl Transactor for TenantUnit {
+    type Transaction<'t> = PgTrxUnit<'t>;
+}
+
+impl AutoCommit for TenantUnit {}
+
+#[async_trait]
+impl DbUnit for TenantUnit {
+    async fn transaction<'s>(&'s mut self) -> Result<Self::Transaction<'s>, RepositoryError> {
+        DbUnit::transaction(&mut **self).await
+    }
+
+    async fn transaction_with<'s>(
+        &'s mut self,
+        options: TransactionOptions,
+    ) -> Result<Self::Transaction<'s>, RepositoryError> {
+        DbUnit::transaction_with(&mut **self, options).await
+    }
+}
+
+#[cfg(feature = "native_async")]
+impl native::Transactor for TenantUnit {
+    type Transaction<'t> = PgTrxUnit<'t>;
+}
+
+#[cfg(feature = "native_async")]
+impl native::DbUnit for TenantUnit {
+    async fn transaction<'s>(&'s mut self) -> Result<PgTrxUnit<'s>, RepositoryError> {
+        native::DbUnit::transaction(&mut **self).await
+    }
+
+    async fn transaction_with<'s>(
+        &'s mut self,
+        options: TransactionOptions,
+    ) -> Result<PgTrxUnit<'s>, RepositoryError> {
+        native::DbUnit::transaction_with(&mut **self, options).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accepts_tenant_schemas() {
+        assert_eq!(SchemaName::new("tenant_1").unwrap().as_str(), "tenant_1");
+    }
+
+    #[test]
+    fn rejects_invalid_schemas() {
+        for name in [
+            "",
+            "Tenant",
+            "tenant.other",
+            "tenant\"",
+            "pg_catalog",
+            "pg_temp",
+        ] {
+            assert!(
+                matches!(
+                    SchemaName::new(name),
+                    Err(RepositoryError::InvalidIdentifier(_))
+                ),
+                "{name}"
+            );
+        }
+    }
+}
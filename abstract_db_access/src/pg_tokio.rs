@@ -1,14 +1,30 @@
+use std::{future::Future, sync::Arc};
+
 use async_trait::async_trait;
-use tokio_postgres::{Client, GenericClient, Transaction};
+use tokio_postgres::{types::ToSql, Client, GenericClient, Row, Statement, Transaction};
 
 use super::{
-    DbAccess, DbUnit, RepositoryError, SavePoint, TransactionState, TransactionUnit, Transactor,
+    pg, AutoCommit, DbAccess, DbUnit, InTransaction, QueryStream, RepositoryError, RowStream,
+    SavePoint, TransactionOptions, TransactionState, TransactionUnit, Transactor,
 };
 
+#[cfg(feature = "native_async")]
+use super::native;
+
+pub mod lazy;
+pub mod statement_cache;
+
+use statement_cache::StatementCache;
+
 #[derive(Debug, Clone)]
 pub struct PgClient<C: GenericClient> {
     client: C,
     state: TransactionState,
+    /// Shared by the unit with its transactions, since they run on the same connection
+    statements: Arc<StatementCache>,
+    cancel: pg::CancelConnector,
+    /// Cancels the running query at the deadline, held by the transaction but not its save points
+    _watchdog: Option<Arc<pg::Watchdog>>,
 }
 
 pub type PgUnit = PgClient<Client>;
@@ -21,9 +37,26 @@ pub enum OpenTransaction<'c, C: GenericClient> {
 
 impl<C: GenericClient> PgClient<C> {
     pub fn new(client: C) -> Self {
+        Self::with_statement_cache(client, statement_cache::DEFAULT_CAPACITY)
+    }
+
+    /// Creates the unit caching up to `capacity` prepared statements.
+    pub fn with_statement_cache(client: C, capacity: usize) -> Self {
         Self {
             client,
             state: TransactionState::new(),
+            statements: Arc::new(StatementCache::new(capacity)),
+            cancel: pg::CancelConnector::default(),
+            _watchdog: None,
+        }
+    }
+
+    /// Sends the cancel requests of the deadlines through the `connector`, required when the
+    /// server only accepts TLS connections.
+    pub fn with_cancel_connector(self, connector: pg::CancelConnector) -> Self {
+        Self {
+            cancel: connector,
+            ..self
         }
     }
 
@@ -31,12 +64,13 @@ impl<C: GenericClient> PgClient<C> {
         Self {
             client: trx,
             state: TransactionState::from_open_transaction(depth),
+            statements: Arc::default(),
+            cancel: pg::CancelConnector::default(),
+            _watchdog: None,
         }
     }
 
-    pub async fn make_transaction<'s>(
-        &'s mut self,
-    ) -> Result<OpenTransaction<'s, C>, RepositoryError> {
+    pub async fn make_transaction(&mut self) -> Result<OpenTransaction<'_, C>, RepositoryError> {
         if self.state.open {
             Ok(OpenTransaction::Reused(&mut self.client))
         } else {
@@ -52,6 +86,87 @@ impl<C: GenericClient> PgClient<C> {
     pub fn transaction_state(&self) -> &TransactionState {
         &self.state
     }
+
+    pub fn statement_cache(&self) -> &StatementCache {
+        &self.statements
+    }
+
+    /// Prepares the `sql`, reusing the statement previously prepared on this connection.
+    pub async fn prepare_cached(&self, sql: &str) -> Result<Statement, RepositoryError> {
+        prepare_cached(&self.client, &self.statements, sql).await
+    }
+
+    /// Like `query`, through the cached statement of the `sql`.
+    pub async fn query_cached(
+        &self,
+        sql: &str,
+        params: &[&(dyn ToSql + Sync)],
+    ) -> Result<Vec<Row>, RepositoryError> {
+        self.run_cached(sql, |statement| async move {
+            self.client.query(&statement, params).await
+        })
+        .await
+    }
+
+    /// Like `execute`, through the cached statement of the `sql`.
+    pub async fn execute_cached(
+        &self,
+        sql: &str,
+        params: &[&(dyn ToSql + Sync)],
+    ) -> Result<u64, RepositoryError> {
+        self.run_cached(sql, |statement| async move {
+            self.client.execute(&statement, params).await
+        })
+        .await
+    }
+
+    /// Runs the cached statement of the `sql`.
+    ///
+    /// A statement made stale by a schema change (`cached plan must not change result type`) is
+    /// invalidated. Outside a transaction it is prepared again and retried once, inside it the
+    /// error is returned since the transaction is already aborted.
+    async fn run_cached<T, F, Fut>(&self, sql: &str, run: F) -> Result<T, RepositoryError>
+    where
+        F: Fn(Statement) -> Fut,
+        Fut: Future<Output = Result<T, tokio_postgres::Error>>,
+    {
+        let statement = self.prepare_cached(sql).await?;
+
+        match run(statement).await.map_err(RepositoryError::from) {
+            Err(err) if statement_cache::is_stale_statement(&err) => {
+                self.statements.invalidate(sql);
+                if self.state.is_open() {
+                    return Err(err);
+                }
+
+                let statement = self.prepare_cached(sql).await?;
+                Ok(run(statement).await?)
+            }
+            res => res,
+        }
+    }
+
+    /// Deadline bounding the operations of this transaction
+    pub fn deadline(&self) -> pg::Deadline {
+        self.deadline_of(&self.state)
+    }
+
+    fn deadline_of(&self, state: &TransactionState) -> pg::Deadline {
+        let token = self.client.client().cancel_token();
+        pg::Deadline::new(token, self.cancel.clone(), state.deadline())
+    }
+}
+
+impl<C: GenericClient + Sync> pg::PgConnection for PgClient<C> {
+    type Client = C;
+
+    fn pg_client(&self) -> &Self::Client {
+        &self.client
+    }
+
+    fn is_transaction(&self) -> bool {
+        self.state.is_open()
+    }
 }
 
 impl<C: GenericClient> DbAccess for PgClient<C> {
@@ -62,27 +177,41 @@ impl<C: GenericClient> Transactor for PgClient<C> {
     type Transaction<'t> = PgTrxUnit<'t>;
 }
 
+impl AutoCommit for PgUnit {}
+
+impl<'t> InTransaction for PgTrxUnit<'t> {}
+
+impl<C: GenericClient + Sync> QueryStream for PgClient<C> {
+    type Row = Row;
+    type Query<'q> = pg::PgQuery<'q>;
+
+    fn query_stream<'s, 'q: 's>(&'s mut self, query: Self::Query<'q>) -> RowStream<'s, Self::Row> {
+        pg::query_stream(&self.client, query)
+    }
+}
+
 #[async_trait]
 impl DbUnit for PgUnit {
     async fn transaction<'s>(&'s mut self) -> Result<Self::Transaction<'s>, RepositoryError> {
-        let trx = self.client.transaction().await?;
-        Ok(Self::Transaction {
-            client: trx,
-            state: TransactionState::from_open_transaction(0),
-        })
+        begin(self).await
+    }
+
+    async fn transaction_with<'s>(
+        &'s mut self,
+        options: TransactionOptions,
+    ) -> Result<Self::Transaction<'s>, RepositoryError> {
+        begin_with(self, options).await
     }
 }
 
 #[async_trait]
 impl<'t> TransactionUnit for PgTrxUnit<'t> {
     async fn commit(self) -> Result<(), RepositoryError> {
-        self.client.commit().await?;
-        Ok(())
+        commit(self).await
     }
 
     async fn rollback(self) -> Result<(), RepositoryError> {
-        self.client.rollback().await?;
-        Ok(())
+        rollback(self).await
     }
 }
 
@@ -92,12 +221,127 @@ impl<'t> SavePoint for PgTrxUnit<'t> {
         &'s mut self,
         name: &str,
     ) -> Result<Self::Transaction<'s>, RepositoryError> {
-        let depth = self.depth() + 1;
-        let point = self.client.savepoint(name).await?;
-        Ok(Self::Transaction::from_transaction(point, depth))
+        save_point(self, name).await
+    }
+
+    fn depth(&self) -> u32 {
+        self.state.depth
+    }
+}
+
+#[cfg(feature = "native_async")]
+impl<C: GenericClient> native::Transactor for PgClient<C> {
+    type Transaction<'t> = PgTrxUnit<'t>;
+}
+
+#[cfg(feature = "native_async")]
+impl native::DbUnit for PgUnit {
+    async fn transaction<'s>(&'s mut self) -> Result<PgTrxUnit<'s>, RepositoryError> {
+        begin(self).await
+    }
+
+    async fn transaction_with<'s>(
+        &'s mut self,
+        options: TransactionOptions,
+    ) -> Result<PgTrxUnit<'s>, RepositoryError> {
+        begin_with(self, options).await
+    }
+}
+
+#[cfg(feature = "native_async")]
+impl<'t> native::TransactionUnit for PgTrxUnit<'t> {
+    async fn commit(self) -> Result<(), RepositoryError> {
+        commit(self).await
+    }
+
+    async fn rollback(self) -> Result<(), RepositoryError> {
+        rollback(self).await
+    }
+}
+
+#[cfg(feature = "native_async")]
+impl<'t> native::SavePoint for PgTrxUnit<'t> {
+    async fn save_point<'s>(&'s mut self, name: &str) -> Result<PgTrxUnit<'s>, RepositoryError> {
+        save_point(self, name).await
     }
 
     fn depth(&self) -> u32 {
         self.state.depth
     }
 }
+
+/// Prepares the `sql` on the `client`, reusing the statement found in the `statements`.
+async fn prepare_cached<C: GenericClient>(
+    client: &C,
+    statements: &StatementCache,
+    sql: &str,
+) -> Result<Statement, RepositoryError> {
+    if let Some(statement) = statements.get(sql) {
+        return Ok(statement);
+    }
+
+    let statement = client.prepare(sql).await?;
+    statements.insert(sql, statement.clone());
+    Ok(statement)
+}
+
+async fn begin(unit: &mut PgUnit) -> Result<PgTrxUnit<'_>, RepositoryError> {
+    let trx = unit.client.transaction().await?;
+    Ok(PgTrxUnit {
+        client: trx,
+        state: TransactionState::from_open_transaction(0),
+        statements: unit.statements.clone(),
+        cancel: unit.cancel.clone(),
+        _watchdog: None,
+    })
+}
+
+async fn begin_with(
+    unit: &mut PgUnit,
+    options: TransactionOptions,
+) -> Result<PgTrxUnit<'_>, RepositoryError> {
+    let state = TransactionState::from_options(0, &options);
+    let deadline = unit.deadline_of(&state);
+    let trx = deadline
+        .run(pg::begin(&mut unit.client, options.settings()))
+        .await?;
+    Ok(PgTrxUnit {
+        client: trx,
+        state,
+        statements: unit.statements.clone(),
+        cancel: unit.cancel.clone(),
+        _watchdog: deadline.watch().map(Arc::new),
+    })
+}
+
+async fn commit(trx: PgTrxUnit<'_>) -> Result<(), RepositoryError> {
+    if trx.state.is_expired() {
+        // dropping the transaction sends the rollback without waiting for the server
+        drop(trx.client);
+        return Err(RepositoryError::Timeout);
+    }
+
+    let deadline = trx.deadline();
+    deadline.run(trx.client.commit()).await
+}
+
+async fn rollback(trx: PgTrxUnit<'_>) -> Result<(), RepositoryError> {
+    let deadline = trx.deadline();
+    deadline.run(trx.client.rollback()).await
+}
+
+async fn save_point<'s>(
+    trx: &'s mut PgTrxUnit<'_>,
+    name: &str,
+) -> Result<PgTrxUnit<'s>, RepositoryError> {
+    let state = trx.state.nested();
+    let deadline = trx.deadline();
+    let point = deadline.run(trx.client.savepoint(name)).await?;
+    Ok(PgTrxUnit {
+        client: point,
+        state,
+        statements: trx.statements.clone(),
+        cancel: trx.cancel.clone(),
+        _watchdog: None,
+    })
+}
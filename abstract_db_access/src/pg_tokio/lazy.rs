@@ -0,0 +1,475 @@
+use std::{
+    future::{self, Future},
+    mem,
+    ops::{Deref, DerefMut},
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+};
+
+use async_trait::async_trait;
+use futures_util::task::noop_waker_ref;
+use tokio_postgres::{types::ToSql, Client, Row};
+
+use super::{statement_cache::StatementCache, PgUnit};
+#[cfg(feature = "native_async")]
+use crate::native;
+use crate::{
+    pg, AutoCommit, DbAccess, DbUnit, InTransaction, QueryStream, RepositoryError, RowStream,
+    SavePoint, TransactionOptions, TransactionState, TransactionUnit, Transactor,
+};
+
+/// Unit whose transactions are begun lazily.
+///
+/// Opening a transaction does not reach the server. `BEGIN`, along with the settings of the
+/// transaction, is queued on the connection when the [`LazyTrxUnit`] hands out its client for
+/// the first statement, and goes out right before that statement without waiting for the server
+/// to answer. A transaction that issued no statement is committed or rolled back without a
+/// round trip.
+pub struct LazyUnit {
+    unit: PgUnit,
+}
+
+impl LazyUnit {
+    pub fn new(unit: PgUnit) -> Self {
+        Self { unit }
+    }
+
+    pub fn into_inner(self) -> PgUnit {
+        self.unit
+    }
+}
+
+impl Deref for LazyUnit {
+    type Target = PgUnit;
+
+    fn deref(&self) -> &Self::Target {
+        &self.unit
+    }
+}
+
+impl DerefMut for LazyUnit {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.unit
+    }
+}
+
+impl pg::PgConnection for LazyUnit {
+    type Client = Client;
+
+    fn pg_client(&self) -> &Self::Client {
+        self.unit.client()
+    }
+
+    fn is_transaction(&self) -> bool {
+        false
+    }
+}
+
+impl DbAccess for LazyUnit {
+    type Connection = Client;
+}
+
+impl Transactor for LazyUnit {
+    type Transaction<'t> = LazyTrxUnit<'t>;
+}
+
+impl AutoCommit for LazyUnit {}
+
+#[async_trait]
+impl DbUnit for LazyUnit {
+    async fn transaction<'s>(&'s mut self) -> Result<Self::Transaction<'s>, RepositoryError> {
+        Ok(LazyTrxUnit::new(&mut self.unit, TransactionOptions::new()))
+    }
+
+    async fn transaction_with<'s>(
+        &'s mut self,
+        options: TransactionOptions,
+    ) -> Result<Self::Transaction<'s>, RepositoryError> {
+        Ok(LazyTrxUnit::new(&mut self.unit, options))
+    }
+}
+
+#[cfg(feature = "native_async")]
+impl native::Transactor for LazyUnit {
+    type Transaction<'t> = LazyTrxUnit<'t>;
+}
+
+#[cfg(feature = "native_async")]
+impl native::DbUnit for LazyUnit {
+    async fn transaction<'s>(&'s mut self) -> Result<LazyTrxUnit<'s>, RepositoryError> {
+        Ok(LazyTrxUnit::new(&mut self.unit, TransactionOptions::new()))
+    }
+
+    async fn transaction_with<'s>(
+        &'s mut self,
+        options: TransactionOptions,
+    ) -> Result<LazyTrxUnit<'s>, RepositoryError> {
+        Ok(LazyTrxUnit::new(&mut self.unit, options))
+    }
+}
+
+type SendFuture<'c> = Pin<Box<dyn Future<Output = Result<(), tokio_postgres::Error>> + Send + 'c>>;
+
+enum Stage<'t> {
+    /// Holds the settings applied along with the deferred `BEGIN`
+    Pending(Vec<(String, String)>),
+    Begun(Begun<'t>),
+}
+
+/// `BEGIN` queued on the connection
+struct Begun<'t> {
+    /// Response of `BEGIN`, read by the first operation awaiting it
+    response: Option<SendFuture<'t>>,
+    /// Indicates if `BEGIN` failed, its error returned by the operation that read the response
+    failed: bool,
+    /// Cancels the running query at the deadline, held by the transaction but not its save points
+    _watchdog: Option<pg::Watchdog>,
+}
+
+/// Transaction of a [`LazyUnit`], begun by its first statement.
+///
+/// The repositories run their statements through [`pg::PgConnection::pg_client`], which queues
+/// `BEGIN` the first time. Its response is read by the commit, or by the cached statement
+/// helpers and [`SavePoint::save_point`], which return the error `BEGIN` failed with. The
+/// statements run in the meantime fail as the transaction is aborted.
+///
+/// Dropping the transaction without commit rolls it back, if it was begun.
+pub struct LazyTrxUnit<'t> {
+    client: &'t Client,
+    stage: Mutex<Stage<'t>>,
+    /// Name of the save point, `None` for the transaction
+    save_point: Option<String>,
+    /// Indicates if the transaction was committed or rolled back
+    finished: bool,
+    state: TransactionState,
+    statements: Arc<StatementCache>,
+    cancel: pg::CancelConnector,
+}
+
+impl<'t> LazyTrxUnit<'t> {
+    fn new(unit: &'t mut PgUnit, options: TransactionOptions) -> Self {
+        Self {
+            client: &unit.client,
+            stage: Mutex::new(Stage::Pending(options.settings().to_vec())),
+            save_point: None,
+            finished: false,
+            state: TransactionState::from_options(0, &options),
+            statements: unit.statements.clone(),
+            cancel: unit.cancel.clone(),
+        }
+    }
+
+    /// Indicates if `BEGIN` was sent to the server
+    pub fn is_begun(&self) -> bool {
+        matches!(*self.stage.lock().unwrap(), Stage::Begun(_))
+    }
+
+    pub fn transaction_state(&self) -> &TransactionState {
+        &self.state
+    }
+
+    /// Deadline bounding the operations of this transaction
+    pub fn deadline(&self) -> pg::Deadline {
+        pg::Deadline::new(
+            self.client.cancel_token(),
+            self.cancel.clone(),
+            self.state.deadline(),
+        )
+    }
+
+    /// Like [`PgTrxUnit::query_cached`](super::PgClient::query_cached), beginning the transaction
+    /// first.
+    pub async fn query_cached(
+        &self,
+        sql: &str,
+        params: &[&(dyn ToSql + Sync)],
+    ) -> Result<Vec<Row>, RepositoryError> {
+        self.wait_begun().await?;
+        let statement = super::prepare_cached(self.client, &self.statements, sql).await?;
+        let res = self.client.query(&statement, params).await;
+        self.invalidate_stale(sql, res)
+    }
+
+    /// Like [`PgTrxUnit::execute_cached`](super::PgClient::execute_cached), beginning the
+    /// transaction first.
+    pub async fn execute_cached(
+        &self,
+        sql: &str,
+        params: &[&(dyn ToSql + Sync)],
+    ) -> Result<u64, RepositoryError> {
+        self.wait_begun().await?;
+        let statement = super::prepare_cached(self.client, &self.statements, sql).await?;
+        let res = self.client.execute(&statement, params).await;
+        self.invalidate_stale(sql, res)
+    }
+
+    /// Queues `BEGIN` and the settings of the transaction, if not done yet.
+    fn begin(&self) {
+        let mut stage = self.stage.lock().unwrap();
+        let settings = match &mut *stage {
+            Stage::Pending(settings) => mem::take(settings),
+            Stage::Begun(_) => return,
+        };
+
+        let mut sql = String::from("BEGIN;");
+        for (name, value) in &settings {
+            sql.push_str(&format!(
+                " SELECT set_config({}, {}, true);",
+                quote_literal(name),
+                quote_literal(value)
+            ));
+        }
+
+        *stage = Stage::Begun(Begun {
+            response: Some(send(self.client, sql)),
+            failed: false,
+            _watchdog: self.deadline().watch(),
+        });
+    }
+
+    /// Begins the transaction and waits for the server to answer `BEGIN`.
+    async fn wait_begun(&self) -> Result<(), RepositoryError> {
+        self.begin();
+
+        let response = match &mut *self.stage.lock().unwrap() {
+            Stage::Begun(begun) if begun.failed => return Err(aborted()),
+            Stage::Begun(begun) => begun.response.take(),
+            Stage::Pending(_) => None,
+        };
+
+        if let Some(response) = response {
+            if let Err(err) = self.deadline().run(response).await {
+                if let Stage::Begun(begun) = &mut *self.stage.lock().unwrap() {
+                    begun.failed = true;
+                }
+                return Err(err);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Takes the queued `BEGIN` out of the finished transaction, `None` if it was not begun.
+    fn take_begun(&mut self) -> Option<Begun<'t>> {
+        match mem::replace(self.stage.get_mut().unwrap(), Stage::Pending(Vec::new())) {
+            Stage::Begun(begun) => Some(begun),
+            Stage::Pending(_) => None,
+        }
+    }
+
+    fn invalidate_stale<T>(
+        &self,
+        sql: &str,
+        res: Result<T, tokio_postgres::Error>,
+    ) -> Result<T, RepositoryError> {
+        let res = res.map_err(RepositoryError::from);
+        if matches!(&res, Err(err) if super::statement_cache::is_stale_statement(err)) {
+            self.statements.invalidate(sql);
+        }
+        res
+    }
+
+    fn commit_sql(&self) -> String {
+        match &self.save_point {
+            Some(name) => format!("RELEASE {name}"),
+            None => "COMMIT".into(),
+        }
+    }
+
+    fn rollback_sql(&self) -> String {
+        match &self.save_point {
+            Some(name) => format!("ROLLBACK TO {name}"),
+            None => "ROLLBACK".into(),
+        }
+    }
+}
+
+/// Queues the `sql` on the connection, returning the future reading its response.
+///
+/// The request is sent by the first poll of the client future, done here with a no-op waker.
+/// Dropping the returned future discards the response, as the drop of the tokio-postgres
+/// transactions does.
+fn send(client: &Client, sql: String) -> SendFuture<'_> {
+    let mut response: SendFuture<'_> = Box::pin(async move { client.batch_execute(&sql).await });
+    match response
+        .as_mut()
+        .poll(&mut Context::from_waker(noop_waker_ref()))
+    {
+        Poll::Ready(res) => Box::pin(future::ready(res)),
+        Poll::Pending => response,
+    }
+}
+
+fn quote_literal(value: &str) -> String {
+    format!("E'{}'", value.replace('\\', "\\\\").replace('\'', "''"))
+}
+
+fn aborted() -> RepositoryError {
+    RepositoryError::Unknown("the transaction is aborted, `BEGIN` failed".into())
+}
+
+impl<'t> Drop for LazyTrxUnit<'t> {
+    fn drop(&mut self) {
+        if !self.finished && self.is_begun() {
+            drop(send(self.client, self.rollback_sql()));
+        }
+    }
+}
+
+impl<'t> pg::PgConnection for LazyTrxUnit<'t> {
+    type Client = Client;
+
+    /// Queues `BEGIN` before handing out the client, the first time.
+    fn pg_client(&self) -> &Self::Client {
+        self.begin();
+        self.client
+    }
+
+    fn is_transaction(&self) -> bool {
+        true
+    }
+}
+
+impl<'t> DbAccess for LazyTrxUnit<'t> {
+    type Connection = Client;
+}
+
+impl<'t> Transactor for LazyTrxUnit<'t> {
+    type Transaction<'s> = LazyTrxUnit<'s>;
+}
+
+impl<'t> InTransaction for LazyTrxUnit<'t> {}
+
+impl<'t> QueryStream for LazyTrxUnit<'t> {
+    type Row = Row;
+    type Query<'q> = pg::PgQuery<'q>;
+
+    fn query_stream<'s, 'q: 's>(&'s mut self, query: Self::Query<'q>) -> RowStream<'s, Self::Row> {
+        pg::query_stream(pg::PgConnection::pg_client(self), query)
+    }
+}
+
+#[async_trait]
+impl<'t> TransactionUnit for LazyTrxUnit<'t> {
+    async fn commit(self) -> Result<(), RepositoryError> {
+        commit(self).await
+    }
+
+    async fn rollback(self) -> Result<(), RepositoryError> {
+        rollback(self).await
+    }
+}
+
+#[async_trait]
+impl<'t> SavePoint for LazyTrxUnit<'t> {
+    async fn save_point<'s>(
+        &'s mut self,
+        name: &str,
+    ) -> Result<Self::Transaction<'s>, RepositoryError> {
+        save_point(self, name).await
+    }
+
+    fn depth(&self) -> u32 {
+        self.state.depth()
+    }
+}
+
+#[cfg(feature = "native_async")]
+impl<'t> native::Transactor for LazyTrxUnit<'t> {
+    type Transaction<'s> = LazyTrxUnit<'s>;
+}
+
+#[cfg(feature = "native_async")]
+impl<'t> native::TransactionUnit for LazyTrxUnit<'t> {
+    async fn commit(self) -> Result<(), RepositoryError> {
+        commit(self).await
+    }
+
+    async fn rollback(self) -> Result<(), RepositoryError> {
+        rollback(self).await
+    }
+}
+
+#[cfg(feature = "native_async")]
+impl<'t> native::SavePoint for LazyTrxUnit<'t> {
+    async fn save_point<'s>(&'s mut self, name: &str) -> Result<LazyTrxUnit<'s>, RepositoryError> {
+        save_point(self, name).await
+    }
+
+    fn depth(&self) -> u32 {
+        self.state.depth()
+    }
+}
+
+async fn commit(mut trx: LazyTrxUnit<'_>) -> Result<(), RepositoryError> {
+    trx.finished = true;
+    let begun = trx.take_begun();
+
+    if trx.state.is_expired() {
+        if begun.is_some() {
+            // queued without waiting for the server, like the drop of the transaction
+            drop(send(trx.client, trx.rollback_sql()));
+        }
+        return Err(RepositoryError::Timeout);
+    }
+
+    let begun = match begun {
+        Some(begun) => begun,
+        None => return Ok(()),
+    };
+
+    // NOTE: `COMMIT` is queued before reading the response of `BEGIN`, so the transaction is
+    // not left open if the commit is dropped. The server rolls back a failed transaction.
+    let deadline = trx.deadline();
+    let committed = send(trx.client, trx.commit_sql());
+    if begun.failed {
+        return Err(aborted());
+    }
+    if let Some(response) = begun.response {
+        deadline.run(response).await?;
+    }
+    deadline.run(committed).await
+}
+
+async fn rollback(mut trx: LazyTrxUnit<'_>) -> Result<(), RepositoryError> {
+    trx.finished = true;
+    let begun = match trx.take_begun() {
+        Some(begun) => begun,
+        None => return Ok(()),
+    };
+
+    let deadline = trx.deadline();
+    let rolled_back = send(trx.client, trx.rollback_sql());
+    if let Some(response) = begun.response {
+        // the transaction is rolled back whether `BEGIN` failed or not
+        let _ = deadline.run(response).await;
+    }
+    deadline.run(rolled_back).await
+}
+
+async fn save_point<'s>(
+    trx: &'s mut LazyTrxUnit<'_>,
+    name: &str,
+) -> Result<LazyTrxUnit<'s>, RepositoryError> {
+    trx.wait_begun().await?;
+
+    let deadline = trx.deadline();
+    deadline
+        .run(trx.client.batch_execute(&format!("SAVEPOINT {name}")))
+        .await?;
+
+    Ok(LazyTrxUnit {
+        client: trx.client,
+        stage: Mutex::new(Stage::Begun(Begun {
+            response: None,
+            failed: false,
+            _watchdog: None,
+        })),
+        save_point: Some(name.into()),
+        finished: false,
+        state: trx.state.nested(),
+        statements: trx.statements.clone(),
+        cancel: trx.cancel.clone(),
+    })
+}
@@ -0,0 +1,239 @@
+use std::{collections::HashMap, fmt, sync::Mutex};
+
+use tokio_postgres::{error::SqlState, Statement};
+
+use crate::RepositoryError;
+
+/// Default number of statements kept by the cache of a unit
+pub const DEFAULT_CAPACITY: usize = 128;
+
+/// Counters of the statement cache
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    /// Lookups that found a prepared statement
+    pub hits: u64,
+    /// Lookups that needed to prepare the statement
+    pub misses: u64,
+    /// Statements evicted or invalidated
+    pub evictions: u64,
+    /// Statements currently cached
+    pub len: usize,
+}
+
+struct CachedStatement<S> {
+    statement: S,
+    last_used: u64,
+}
+
+/// Least recently used statements, generic over the statement for the tests
+struct CacheInner<S> {
+    statements: HashMap<String, CachedStatement<S>>,
+    clock: u64,
+    stats: CacheStats,
+}
+
+impl<S> Default for CacheInner<S> {
+    fn default() -> Self {
+        Self {
+            statements: HashMap::new(),
+            clock: 0,
+            stats: CacheStats::default(),
+        }
+    }
+}
+
+impl<S: Clone> CacheInner<S> {
+    fn get(&mut self, sql: &str) -> Option<S> {
+        self.clock += 1;
+        let clock = self.clock;
+
+        match self.statements.get_mut(sql) {
+            Some(cached) => {
+                cached.last_used = clock;
+                self.stats.hits += 1;
+                Some(cached.statement.clone())
+            }
+            None => {
+                self.stats.misses += 1;
+                None
+            }
+        }
+    }
+
+    fn insert(&mut self, sql: &str, statement: S, capacity: usize) {
+        if capacity == 0 {
+            return;
+        }
+
+        self.clock += 1;
+        let last_used = self.clock;
+
+        if !self.statements.contains_key(sql) && self.statements.len() >= capacity {
+            // NOTE: linear scan, the cache is meant to be small enough to not justify
+            // keeping a separate recency list
+            let lru = self
+                .statements
+                .iter()
+                .min_by_key(|(_, cached)| cached.last_used)
+                .map(|(sql, _)| sql.clone());
+
+            if let Some(lru) = lru {
+                self.statements.remove(&lru);
+                self.stats.evictions += 1;
+            }
+        }
+
+        self.statements.insert(
+            sql.to_owned(),
+            CachedStatement {
+                statement,
+                last_used,
+            },
+        );
+    }
+
+    fn invalidate(&mut self, sql: &str) {
+        if self.statements.remove(sql).is_some() {
+            self.stats.evictions += 1;
+        }
+    }
+}
+
+/// Prepared statements of a connection, keyed by the SQL text.
+///
+/// Bounded to `capacity` statements, evicting the least recently used one when full. Evicted
+/// statements are closed on the server once no longer referenced.
+pub struct StatementCache {
+    inner: Mutex<CacheInner<Statement>>,
+    capacity: usize,
+}
+
+impl StatementCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            inner: Mutex::new(CacheInner::default()),
+            capacity,
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        let inner = self.inner.lock().unwrap();
+        CacheStats {
+            len: inner.statements.len(),
+            ..inner.stats
+        }
+    }
+
+    /// Removes the statement prepared for the `sql`.
+    pub fn invalidate(&self, sql: &str) {
+        self.inner.lock().unwrap().invalidate(sql);
+    }
+
+    /// Removes all the prepared statements.
+    pub fn clear(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        let len = inner.statements.len() as u64;
+        inner.statements.clear();
+        inner.stats.evictions += len;
+    }
+
+    pub(crate) fn get(&self, sql: &str) -> Option<Statement> {
+        self.inner.lock().unwrap().get(sql)
+    }
+
+    pub(crate) fn insert(&self, sql: &str, statement: Statement) {
+        self.inner
+            .lock()
+            .unwrap()
+            .insert(sql, statement, self.capacity);
+    }
+}
+
+impl Default for StatementCache {
+    fn default() -> Self {
+        Self::new(DEFAULT_CAPACITY)
+    }
+}
+
+impl fmt::Debug for StatementCache {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("StatementCache")
+            .field("capacity", &self.capacity)
+            .field("stats", &self.stats())
+            .finish()
+    }
+}
+
+/// Indicates if the error was caused by a cached statement made stale by a schema change.
+///
+/// Identified by the server function raising it, as the message is translated according to
+/// `lc_messages`.
+pub(crate) fn is_stale_statement(err: &RepositoryError) -> bool {
+    match err {
+        RepositoryError::TokioPostgres(db_err) => {
+            db_err.code() == &SqlState::FEATURE_NOT_SUPPORTED
+                && db_err.routine() == Some("RevalidateCachedQuery")
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cache(sqls: &[&str], capacity: usize) -> CacheInner<u32> {
+        let mut cache = CacheInner::default();
+        for (idx, sql) in sqls.iter().enumerate() {
+            cache.insert(sql, idx as u32, capacity);
+        }
+        cache
+    }
+
+    #[test]
+    fn evicts_the_least_recently_used_statement() {
+        let mut cache = cache(&["a", "b"], 2);
+        assert_eq!(cache.get("a"), Some(0));
+
+        cache.insert("c", 2, 2);
+
+        assert_eq!(cache.get("b"), None);
+        assert_eq!(cache.get("a"), Some(0));
+        assert_eq!(cache.get("c"), Some(2));
+        assert_eq!(cache.stats.evictions, 1);
+    }
+
+    #[test]
+    fn replaces_a_cached_statement_without_eviction() {
+        let mut cache = cache(&["a", "b"], 2);
+
+        cache.insert("a", 2, 2);
+
+        assert_eq!(cache.get("a"), Some(2));
+        assert_eq!(cache.get("b"), Some(1));
+        assert_eq!(cache.stats.evictions, 0);
+    }
+
+    #[test]
+    fn caches_nothing_without_capacity() {
+        let mut cache = cache(&["a"], 0);
+
+        assert_eq!(cache.get("a"), None);
+        assert_eq!(cache.stats.misses, 1);
+    }
+
+    #[test]
+    fn counts_the_invalidated_statements() {
+        let mut cache = cache(&["a"], 2);
+
+        cache.invalidate("a");
+        cache.invalidate("a");
+
+        assert_eq!(cache.get("a"), None);
+        assert_eq!(cache.stats.evictions, 1);
+    }
+}
@@ -0,0 +1,247 @@
+use super::{Entity, EntityDriver, RepositoryError};
+
+/// Number of rows of `columns` values fitting in the `max_parameters` of a statement
+pub(crate) fn chunk_size(max_parameters: usize, columns: &[&str]) -> usize {
+    (max_parameters / columns.len().max(1)).max(1)
+}
+
+/// Quoted columns separated by commas
+fn column_list<E, D>(columns: &[&str]) -> Result<String, RepositoryError>
+where
+    E: Entity,
+    D: EntityDriver<E> + ?Sized,
+{
+    Ok(columns
+        .iter()
+        .map(|column| D::quote_identifier(column))
+        .collect::<Result<Vec<_>, _>>()?
+        .join(", "))
+}
+
+/// Placeholders of `count` parameters, starting after the `offset` ones, e.g. `($3, $4)`
+fn placeholders<E, D>(offset: usize, count: usize) -> String
+where
+    E: Entity,
+    D: EntityDriver<E> + ?Sized,
+{
+    let params: Vec<_> = (1..=count)
+        .map(|idx| D::placeholder(offset + idx))
+        .collect();
+    format!("({})", params.join(", "))
+}
+
+/// Condition matching the key columns with the parameters after the `offset` ones
+pub(crate) fn key_condition<E, D>(offset: usize) -> Result<String, RepositoryError>
+where
+    E: Entity,
+    D: EntityDriver<E> + ?Sized,
+{
+    Ok(format!(
+        "({}) = {}",
+        column_list::<E, D>(E::KEY)?,
+        placeholders::<E, D>(offset, E::KEY.len())
+    ))
+}
+
+/// Statement inserting `count` entities
+pub(crate) fn insert_statement<E, D>(count: usize) -> Result<String, RepositoryError>
+where
+    E: Entity,
+    D: EntityDriver<E> + ?Sized,
+{
+    let rows: Vec<_> = (0..count)
+        .map(|idx| placeholders::<E, D>(idx * E::COLUMNS.len(), E::COLUMNS.len()))
+        .collect();
+    Ok(format!(
+        "INSERT INTO {} ({}) VALUES {}",
+        D::quote_identifier(E::TABLE)?,
+        column_list::<E, D>(E::COLUMNS)?,
+        rows.join(", ")
+    ))
+}
+
+/// Statement selecting the entities of `count` keys
+pub(crate) fn find_many_statement<E, D>(count: usize) -> Result<String, RepositoryError>
+where
+    E: Entity,
+    D: EntityDriver<E> + ?Sized,
+{
+    let key_sets: Vec<_> = (0..count)
+        .map(|idx| placeholders::<E, D>(idx * E::KEY.len(), E::KEY.len()))
+        .collect();
+    Ok(format!(
+        "SELECT {} FROM {} WHERE ({}) IN ({})",
+        column_list::<E, D>(E::COLUMNS)?,
+        D::quote_identifier(E::TABLE)?,
+        column_list::<E, D>(E::KEY)?,
+        key_sets.join(", ")
+    ))
+}
+
+/// Statement updating an entity from the values of its columns.
+///
+/// The key columns are matched and the other ones are assigned. The version column is matched
+/// and incremented, the statement returning the new version.
+pub(crate) fn update_statement<E, D>() -> Result<String, RepositoryError>
+where
+    E: Entity,
+    D: EntityDriver<E> + ?Sized,
+{
+    let param = |column: &str| {
+        E::COLUMNS
+            .iter()
+            .position(|candidate| *candidate == column)
+            .map(|idx| D::placeholder(idx + 1))
+            .ok_or_else(|| {
+                RepositoryError::Unknown(
+                    format!("column {column} is not part of the columns of {}", E::TABLE).into(),
+                )
+            })
+    };
+
+    let mut assignments = Vec::with_capacity(E::COLUMNS.len());
+    for column in E::COLUMNS {
+        if !E::KEY.contains(column) && Some(*column) != E::VERSION {
+            assignments.push(format!(
+                "{} = {}",
+                D::quote_identifier(column)?,
+                param(column)?
+            ));
+        }
+    }
+
+    let keys = E::KEY
+        .iter()
+        .map(|key| param(key))
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut condition = format!("({}) = ({})", column_list::<E, D>(E::KEY)?, keys.join(", "));
+
+    let mut returning = String::new();
+    if let Some(version) = E::VERSION {
+        let param = param(version)?;
+        let version = D::quote_identifier(version)?;
+        assignments.push(format!("{version} = {param} + 1"));
+        condition.push_str(&format!(" AND {version} = {param}"));
+        returning = format!(" RETURNING {version}");
+    }
+
+    if assignments.is_empty() {
+        return Err(RepositoryError::Unknown(
+            format!("{} has no column to update besides its key", E::TABLE).into(),
+        ));
+    }
+
+    Ok(format!(
+        "UPDATE {} SET {} WHERE {condition}{returning}",
+        D::quote_identifier(E::TABLE)?,
+        assignments.join(", "),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use async_trait::async_trait;
+
+    use super::*;
+    use crate::{Bind, DbAccess};
+
+    struct Account;
+
+    impl Entity for Account {
+        type Key = i64;
+
+        const TABLE: &'static str = "account";
+        const KEY: &'static [&'static str] = &["id"];
+        const COLUMNS: &'static [&'static str] = &["id", "balance", "version"];
+        const VERSION: Option<&'static str> = Some("version");
+    }
+
+    struct UserTag;
+
+    impl Entity for UserTag {
+        type Key = (i64, String);
+
+        const TABLE: &'static str = "user_tag";
+        const KEY: &'static [&'static str] = &["user_id", "tag"];
+        const COLUMNS: &'static [&'static str] = &["user_id", "tag"];
+    }
+
+    /// Driver writing `?<idx>` placeholders, never running the statements
+    struct Driver;
+
+    impl DbAccess for Driver {
+        type Connection = ();
+    }
+
+    #[async_trait]
+    impl<E: Entity> EntityDriver<E> for Driver {
+        const MAX_PARAMETERS: usize = 5;
+
+        fn placeholder(idx: usize) -> String {
+            format!("?{idx}")
+        }
+
+        fn quote_identifier(name: &str) -> Result<String, RepositoryError> {
+            Ok(format!("\"{name}\""))
+        }
+
+        fn describe_key(_entity: &E) -> String {
+            unreachable!()
+        }
+
+        async fn execute_entity(
+            &mut self,
+            _sql: &str,
+            _binds: &[Bind<'_, E>],
+        ) -> Result<u64, RepositoryError> {
+            unreachable!()
+        }
+
+        async fn query_entities(
+            &self,
+            _sql: &str,
+            _binds: &[Bind<'_, E>],
+        ) -> Result<Vec<E>, RepositoryError> {
+            unreachable!()
+        }
+
+        async fn query_i64(
+            &self,
+            _sql: &str,
+            _binds: &[Bind<'_, E>],
+        ) -> Result<Option<i64>, RepositoryError> {
+            unreachable!()
+        }
+    }
+
+    #[test]
+    fn chunks_fit_the_parameters_limit() {
+        assert_eq!(chunk_size(5, UserTag::KEY), 2);
+        assert_eq!(chunk_size(5, Account::COLUMNS), 1);
+        assert_eq!(chunk_size(1, Account::COLUMNS), 1);
+    }
+
+    #[test]
+    fn finds_composite_keys() {
+        assert_eq!(
+            find_many_statement::<UserTag, Driver>(2).unwrap(),
+            r#"SELECT "user_id", "tag" FROM "user_tag" WHERE ("user_id", "tag") IN ((?1, ?2), (?3, ?4))"#
+        );
+    }
+
+    #[test]
+    fn updates_checking_the_version() {
+        assert_eq!(
+            update_statement::<Account, Driver>().unwrap(),
+            r#"UPDATE "account" SET "balance" = ?2, "version" = ?3 + 1 WHERE ("id") = (?1) AND "version" = ?3 RETURNING "version""#
+        );
+    }
+
+    #[test]
+    fn rejects_updates_of_key_only_entities() {
+        assert!(matches!(
+            update_statement::<UserTag, Driver>(),
+            Err(RepositoryError::Unknown(_))
+        ));
+    }
+}
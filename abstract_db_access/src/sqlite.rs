@@ -0,0 +1,146 @@
+use rusqlite::{Connection, Savepoint, Transaction};
+
+use super::{
+    blocking::{DbUnit, InTransaction, SavePoint, TransactionUnit, Transactor},
+    AutoCommit, DbAccess, RepositoryError, TransactionOptions, TransactionState,
+};
+
+pub type SqliteUnit = Connection;
+
+enum Scope<'t> {
+    Transaction(Transaction<'t>),
+    SavePoint(Savepoint<'t>),
+}
+
+/// Transaction or save point of a SQLite connection.
+///
+/// Dropping the transaction without commit rolls it back.
+pub struct SqliteTrxUnit<'t> {
+    scope: Scope<'t>,
+    pub state: TransactionState,
+}
+
+/// Unit or transaction running the statements of the repositories
+pub trait SqliteConnection {
+    fn connection(&self) -> &Connection;
+
+    /// Indicates if the statements run inside a transaction
+    fn is_transaction(&self) -> bool;
+}
+
+impl SqliteConnection for SqliteUnit {
+    fn connection(&self) -> &Connection {
+        self
+    }
+
+    fn is_transaction(&self) -> bool {
+        false
+    }
+}
+
+impl DbAccess for SqliteUnit {
+    type Connection = Connection;
+}
+
+impl Transactor for SqliteUnit {
+    type Transaction<'t> = SqliteTrxUnit<'t>;
+}
+
+impl AutoCommit for SqliteUnit {}
+
+impl DbUnit for SqliteUnit {
+    fn transaction(&mut self) -> Result<Self::Transaction<'_>, RepositoryError> {
+        DbUnit::transaction_with(self, TransactionOptions::new())
+    }
+
+    /// The settings of the `options` are not supported, SQLite has no configuration parameters
+    /// scoped to a transaction.
+    fn transaction_with(
+        &mut self,
+        options: TransactionOptions,
+    ) -> Result<Self::Transaction<'_>, RepositoryError> {
+        if !options.settings().is_empty() {
+            return Err(RepositoryError::Unknown(
+                "transaction settings are not supported by the sqlite backend".into(),
+            ));
+        }
+
+        let trx = Connection::transaction(self)?;
+        Ok(SqliteTrxUnit {
+            scope: Scope::Transaction(trx),
+            state: TransactionState::from_options(0, &options),
+        })
+    }
+}
+
+impl<'t> SqliteConnection for SqliteTrxUnit<'t> {
+    fn connection(&self) -> &Connection {
+        match &self.scope {
+            Scope::Transaction(trx) => trx,
+            Scope::SavePoint(point) => point,
+        }
+    }
+
+    fn is_transaction(&self) -> bool {
+        true
+    }
+}
+
+impl<'t> DbAccess for SqliteTrxUnit<'t> {
+    type Connection = Connection;
+}
+
+impl<'t> Transactor for SqliteTrxUnit<'t> {
+    type Transaction<'trx> = SqliteTrxUnit<'trx>;
+}
+
+impl<'t> InTransaction for SqliteTrxUnit<'t> {}
+
+impl<'t> TransactionUnit for SqliteTrxUnit<'t> {
+    fn commit(self) -> Result<(), RepositoryError> {
+        if self.state.is_expired() {
+            self.rollback()?;
+            return Err(RepositoryError::Timeout);
+        }
+
+        match self.scope {
+            Scope::Transaction(trx) => trx.commit()?,
+            Scope::SavePoint(point) => point.commit()?,
+        }
+        Ok(())
+    }
+
+    fn rollback(self) -> Result<(), RepositoryError> {
+        match self.scope {
+            Scope::Transaction(trx) => trx.rollback()?,
+            // NOTE: rolling back to the save point keeps it, it is released afterwards
+            Scope::SavePoint(mut point) => {
+                point.rollback()?;
+                point.commit()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<'t> SavePoint for SqliteTrxUnit<'t> {
+    fn save_point(&mut self, name: &str) -> Result<Self::Transaction<'_>, RepositoryError> {
+        if self.state.is_expired() {
+            return Err(RepositoryError::Timeout);
+        }
+
+        let state = self.state.nested();
+        let point = match &mut self.scope {
+            Scope::Transaction(trx) => trx.savepoint_with_name(name)?,
+            Scope::SavePoint(point) => point.savepoint_with_name(name)?,
+        };
+        Ok(SqliteTrxUnit {
+            scope: Scope::SavePoint(point),
+            state,
+        })
+    }
+
+    fn depth(&self) -> u32 {
+        self.state.depth()
+    }
+}
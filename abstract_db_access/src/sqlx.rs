@@ -1,10 +1,117 @@
+use std::{
+    fmt,
+    ops::{Deref, DerefMut},
+};
+
 use async_trait::async_trait;
+use futures_util::{future::BoxFuture, stream::BoxStream, TryStreamExt};
+use sqlx_core::{
+    arguments::IntoArguments,
+    column::ColumnIndex,
+    connection::Connection,
+    database::{Database, HasArguments, HasStatement},
+    decode::Decode,
+    describe::Describe,
+    executor::{Execute, Executor},
+    query::Query,
+    row::Row,
+    transaction::Transaction,
+    types::Type,
+    Either,
+};
 
-use super::{DbAccess, RepositoryError, TransactionUnit, Transactor};
+#[cfg(feature = "native_async")]
+use super::native;
+use super::{
+    AutoCommit, DbAccess, DbDriver, DbUnit, InTransaction, QueryStream, RepositoryError, RowStream,
+    TransactionOptions, TransactionState, TransactionUnit, Transactor,
+};
 
 pub type SqlxUnit<DB> = sqlx_core::pool::PoolConnection<DB>;
 
-pub type SqlxTrxUnit<'t, DB> = sqlx_core::transaction::Transaction<'t, DB>;
+/// Transaction of a sqlx connection.
+///
+/// Dereferences to the sqlx transaction and runs the queries as an [`Executor`]. Dropping the
+/// transaction without commit rolls it back.
+pub struct SqlxTrxUnit<'t, DB: Database> {
+    trx: Transaction<'t, DB>,
+    pub state: TransactionState,
+}
+
+impl<'t, DB: Database> Deref for SqlxTrxUnit<'t, DB> {
+    type Target = Transaction<'t, DB>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.trx
+    }
+}
+
+impl<'t, DB: Database> DerefMut for SqlxTrxUnit<'t, DB> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.trx
+    }
+}
+
+impl<'t, DB: Database> fmt::Debug for SqlxTrxUnit<'t, DB> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SqlxTrxUnit")
+            .field("state", &self.state)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Runs the queries on the connection of the transaction, like the sqlx transactions do.
+impl<'c, 't, DB> Executor<'c> for &'c mut SqlxTrxUnit<'t, DB>
+where
+    DB: Database,
+    for<'e> &'e mut DB::Connection: Executor<'e, Database = DB>,
+{
+    type Database = DB;
+
+    fn fetch_many<'e, 'q: 'e, E: 'q>(
+        self,
+        query: E,
+    ) -> BoxStream<'e, Result<Either<DB::QueryResult, DB::Row>, sqlx_core::error::Error>>
+    where
+        'c: 'e,
+        E: Execute<'q, DB>,
+    {
+        (&mut *self.trx).fetch_many(query)
+    }
+
+    fn fetch_optional<'e, 'q: 'e, E: 'q>(
+        self,
+        query: E,
+    ) -> BoxFuture<'e, Result<Option<DB::Row>, sqlx_core::error::Error>>
+    where
+        'c: 'e,
+        E: Execute<'q, DB>,
+    {
+        (&mut *self.trx).fetch_optional(query)
+    }
+
+    fn prepare_with<'e, 'q: 'e>(
+        self,
+        sql: &'q str,
+        parameters: &'e [DB::TypeInfo],
+    ) -> BoxFuture<'e, Result<<DB as HasStatement<'q>>::Statement, sqlx_core::error::Error>>
+    where
+        'c: 'e,
+    {
+        (&mut *self.trx).prepare_with(sql, parameters)
+    }
+
+    #[doc(hidden)]
+    fn describe<'e, 'q: 'e>(
+        self,
+        query: &'q str,
+    ) -> BoxFuture<'e, Result<Describe<DB>, sqlx_core::error::Error>>
+    where
+        'c: 'e,
+    {
+        (&mut *self.trx).describe(query)
+    }
+}
 
 impl<DB: sqlx_core::database::Database> DbAccess for SqlxUnit<DB> {
     type Connection = Self;
@@ -27,17 +134,71 @@ impl<DB: sqlx_core::database::Database> Transactor for SqlxUnit<DB> {
     type Transaction<'t> = SqlxTrxUnit<'t, DB>;
 }
 
-// NOTE: to begin a transaction is required to consume the connection, and the current API
-// only thoes a mutable borrow of the connection
-// #[async_trait]
-// impl<DB: sqlx_core::database::Database> DbUnit for SqlxUnit<DB> {
-//     async fn transaction<'s>(&'s mut self) -> Result<Self::Transaction<'s>, RepositoryError> {
-//         use sqlx_core::acquire::Acquire;
+impl<DB: sqlx_core::database::Database> AutoCommit for SqlxUnit<DB> {}
+
+impl<DB> QueryStream for SqlxUnit<DB>
+where
+    DB: Database,
+    for<'c> &'c mut DB::Connection: Executor<'c, Database = DB>,
+    for<'q> <DB as HasArguments<'q>>::Arguments: IntoArguments<'q, DB>,
+{
+    type Row = DB::Row;
+    type Query<'q> = Query<'q, DB, <DB as HasArguments<'q>>::Arguments>;
+
+    fn query_stream<'s, 'q: 's>(&'s mut self, query: Self::Query<'q>) -> RowStream<'s, Self::Row> {
+        Box::pin(query.fetch(&mut **self).map_err(RepositoryError::from))
+    }
+}
+
+#[async_trait]
+impl<DB> DbDriver for SqlxUnit<DB>
+where
+    DB: Database,
+    for<'c> &'c mut DB::Connection: Executor<'c, Database = DB>,
+    for<'r> Option<String>: Decode<'r, DB> + Type<DB>,
+    usize: ColumnIndex<DB::Row>,
+{
+    async fn execute_script(&mut self, sql: &str) -> Result<(), RepositoryError> {
+        execute_script::<DB>(self, sql).await
+    }
+
+    async fn query_text(&mut self, sql: &str) -> Result<Vec<Vec<Option<String>>>, RepositoryError> {
+        query_text::<DB>(self, sql).await
+    }
+}
+
+#[async_trait]
+impl<DB: sqlx_core::database::Database> DbUnit for SqlxUnit<DB> {
+    async fn transaction<'s>(&'s mut self) -> Result<Self::Transaction<'s>, RepositoryError> {
+        begin(self).await
+    }
+
+    async fn transaction_with<'s>(
+        &'s mut self,
+        options: TransactionOptions,
+    ) -> Result<Self::Transaction<'s>, RepositoryError> {
+        begin_with(self, options).await
+    }
+}
+
+#[cfg(feature = "native_async")]
+impl<DB: sqlx_core::database::Database> native::Transactor for SqlxUnit<DB> {
+    type Transaction<'t> = SqlxTrxUnit<'t, DB>;
+}
+
+#[cfg(feature = "native_async")]
+impl<DB: sqlx_core::database::Database> native::DbUnit for SqlxUnit<DB> {
+    async fn transaction<'s>(&'s mut self) -> Result<SqlxTrxUnit<'s, DB>, RepositoryError> {
+        begin(self).await
+    }
 
-//         let trx = sqlx_core::pool::PoolConnection::begin(self).await.unwrap();
-//         Ok(trx)
-//     }
-// }
+    async fn transaction_with<'s>(
+        &'s mut self,
+        options: TransactionOptions,
+    ) -> Result<SqlxTrxUnit<'s, DB>, RepositoryError> {
+        begin_with(self, options).await
+    }
+}
 
 impl<'t, DB: sqlx_core::database::Database> DbAccess for SqlxTrxUnit<'t, DB> {
     type Connection = SqlxUnit<DB>;
@@ -50,19 +211,137 @@ impl<'t, DB: sqlx_core::database::Database> Transactor for SqlxTrxUnit<'t, DB> {
     type Transaction<'trx> = SqlxTrxUnit<'trx, DB>;
 }
 
+impl<'t, DB: sqlx_core::database::Database> InTransaction for SqlxTrxUnit<'t, DB> {}
+
+impl<'t, DB> QueryStream for SqlxTrxUnit<'t, DB>
+where
+    DB: Database,
+    for<'c> &'c mut DB::Connection: Executor<'c, Database = DB>,
+    for<'q> <DB as HasArguments<'q>>::Arguments: IntoArguments<'q, DB>,
+{
+    type Row = DB::Row;
+    type Query<'q> = Query<'q, DB, <DB as HasArguments<'q>>::Arguments>;
+
+    fn query_stream<'s, 'q: 's>(&'s mut self, query: Self::Query<'q>) -> RowStream<'s, Self::Row> {
+        Box::pin(query.fetch(&mut *self.trx).map_err(RepositoryError::from))
+    }
+}
+
+#[async_trait]
+impl<'t, DB> DbDriver for SqlxTrxUnit<'t, DB>
+where
+    DB: Database,
+    for<'c> &'c mut DB::Connection: Executor<'c, Database = DB>,
+    for<'r> Option<String>: Decode<'r, DB> + Type<DB>,
+    usize: ColumnIndex<DB::Row>,
+{
+    async fn execute_script(&mut self, sql: &str) -> Result<(), RepositoryError> {
+        execute_script::<DB>(self, sql).await
+    }
+
+    async fn query_text(&mut self, sql: &str) -> Result<Vec<Vec<Option<String>>>, RepositoryError> {
+        query_text::<DB>(self, sql).await
+    }
+}
+
 #[async_trait]
 impl<'t, DB: sqlx_core::database::Database> TransactionUnit for SqlxTrxUnit<'t, DB> {
     async fn commit(self) -> Result<(), RepositoryError> {
-        sqlx_core::transaction::Transaction::commit(self)
-            .await
-            .unwrap();
-        Ok(())
+        commit(self).await
     }
 
     async fn rollback(self) -> Result<(), RepositoryError> {
-        sqlx_core::transaction::Transaction::rollback(self)
-            .await
-            .unwrap();
-        Ok(())
+        rollback(self).await
     }
 }
+
+#[cfg(feature = "native_async")]
+impl<'t, DB: sqlx_core::database::Database> native::Transactor for SqlxTrxUnit<'t, DB> {
+    type Transaction<'trx> = SqlxTrxUnit<'trx, DB>;
+}
+
+#[cfg(feature = "native_async")]
+impl<'t, DB: sqlx_core::database::Database> native::TransactionUnit for SqlxTrxUnit<'t, DB> {
+    async fn commit(self) -> Result<(), RepositoryError> {
+        commit(self).await
+    }
+
+    async fn rollback(self) -> Result<(), RepositoryError> {
+        rollback(self).await
+    }
+}
+
+async fn begin<DB: Database>(
+    unit: &mut SqlxUnit<DB>,
+) -> Result<SqlxTrxUnit<'_, DB>, RepositoryError> {
+    begin_with(unit, TransactionOptions::new()).await
+}
+
+/// Only the deadline of the `options` is supported, checked by the commit: sqlx is not able to
+/// cancel the running query, nor to apply the settings on every database kind.
+async fn begin_with<DB: Database>(
+    unit: &mut SqlxUnit<DB>,
+    options: TransactionOptions,
+) -> Result<SqlxTrxUnit<'_, DB>, RepositoryError> {
+    if !options.settings().is_empty() {
+        return Err(RepositoryError::Unknown(
+            "transaction settings are not supported by the sqlx backend".into(),
+        ));
+    }
+
+    let trx = Connection::begin(&mut **unit).await?;
+    Ok(SqlxTrxUnit {
+        trx,
+        state: TransactionState::from_options(0, &options),
+    })
+}
+
+/// Commits the `trx`, unless its deadline has passed, rolling it back then.
+async fn commit<DB: Database>(trx: SqlxTrxUnit<'_, DB>) -> Result<(), RepositoryError> {
+    if trx.state.is_expired() {
+        rollback(trx).await?;
+        return Err(RepositoryError::Timeout);
+    }
+
+    trx.trx.commit().await?;
+    Ok(())
+}
+
+async fn rollback<DB: Database>(trx: SqlxTrxUnit<'_, DB>) -> Result<(), RepositoryError> {
+    trx.trx.rollback().await?;
+    Ok(())
+}
+
+/// Executes the `sql` without arguments, so the statements can be separated by semicolons.
+async fn execute_script<DB>(conn: &mut DB::Connection, sql: &str) -> Result<(), RepositoryError>
+where
+    DB: Database,
+    for<'c> &'c mut DB::Connection: Executor<'c, Database = DB>,
+{
+    conn.execute(sql).await?;
+    Ok(())
+}
+
+/// Fetches the rows of the `sql`, decoding every column as text.
+///
+/// The columns are decoded by their type, so anything but text columns fails.
+async fn query_text<DB>(
+    conn: &mut DB::Connection,
+    sql: &str,
+) -> Result<Vec<Vec<Option<String>>>, RepositoryError>
+where
+    DB: Database,
+    for<'c> &'c mut DB::Connection: Executor<'c, Database = DB>,
+    for<'r> Option<String>: Decode<'r, DB> + Type<DB>,
+    usize: ColumnIndex<DB::Row>,
+{
+    let rows = conn.fetch_all(sql).await?;
+    rows.iter()
+        .map(|row| {
+            (0..row.columns().len())
+                .map(|idx| row.try_get::<Option<String>, _>(idx))
+                .collect::<Result<_, _>>()
+                .map_err(RepositoryError::from)
+        })
+        .collect()
+}
@@ -0,0 +1,47 @@
+use std::panic::{self, AssertUnwindSafe};
+
+use futures_util::{future::BoxFuture, FutureExt};
+
+use super::{DbUnit, RepositoryError, TransactionUnit, Transactor};
+
+/// Runs the `test` inside a transaction of the `unit` that is always rolled back.
+///
+/// Each test sees its own changes only, so tests running concurrently on different
+/// connections don't interfere with each other, and the database is left as it was found.
+///
+/// The transaction is rolled back even if the test panics, the panic being resumed after.
+/// Code under test that needs to commit can do it on a save point of the transaction.
+pub async fn with_test_transaction<'s, U, F, T>(
+    unit: &'s mut U,
+    test: F,
+) -> Result<T, RepositoryError>
+where
+    U: DbUnit + Send,
+    F: for<'t> FnOnce(&'t mut <U as Transactor>::Transaction<'s>) -> BoxFuture<'t, T>,
+{
+    let mut trx = unit.transaction().await?;
+
+    let res = AssertUnwindSafe(test(&mut trx)).catch_unwind().await;
+
+    trx.rollback().await?;
+
+    match res {
+        Ok(output) => Ok(output),
+        Err(panic) => panic::resume_unwind(panic),
+    }
+}
+
+/// Runs the `test` inside a rolled back transaction of a connection taken from the `pool`.
+///
+/// See [`with_test_transaction`].
+#[cfg(feature = "pg_deadpool")]
+pub async fn with_deadpool_transaction<F, T>(
+    pool: &deadpool_postgres::Pool,
+    test: F,
+) -> Result<T, RepositoryError>
+where
+    F: for<'s, 't> FnOnce(&'t mut crate::pg_deadpool::PgTrxUnit<'s>) -> BoxFuture<'t, T>,
+{
+    let mut unit = pool.get().await?;
+    with_test_transaction(&mut unit, test).await
+}
@@ -0,0 +1,298 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::{Data, DeriveInput, Field, Fields, Lit, Meta, NestedMeta};
+
+/// How a field is read from the row
+enum Source {
+    /// Column of the row, `Default::default()` when missing if `default`
+    Column { name: String, default: bool },
+    /// Built from the whole row
+    Flatten,
+}
+
+fn field_source(field: &Field) -> syn::Result<Source> {
+    let mut rename = None;
+    let mut default = false;
+    let mut flatten = false;
+
+    for attr in field.attrs.iter().filter(|attr| attr.path.is_ident("row")) {
+        let list =
+            match attr.parse_meta()? {
+                Meta::List(list) => list,
+                meta => return Err(syn::Error::new_spanned(
+                    meta,
+                    "expected `#[row(rename = \"...\")]`, `#[row(default)]` or `#[row(flatten)]`",
+                )),
+            };
+
+        for nested in list.nested {
+            match nested {
+                NestedMeta::Meta(Meta::NameValue(meta)) if meta.path.is_ident("rename") => {
+                    match meta.lit {
+                        Lit::Str(name) => rename = Some(name.value()),
+                        lit => return Err(syn::Error::new_spanned(lit, "expected a column name")),
+                    }
+                }
+                NestedMeta::Meta(Meta::Path(path)) if path.is_ident("default") => default = true,
+                NestedMeta::Meta(Meta::Path(path)) if path.is_ident("flatten") => flatten = true,
+                nested => {
+                    return Err(syn::Error::new_spanned(
+                        nested,
+                        "unknown attribute, expected `rename`, `default` or `flatten`",
+                    ))
+                }
+            }
+        }
+    }
+
+    if flatten {
+        if rename.is_some() || default {
+            return Err(syn::Error::new_spanned(
+                field,
+                "`flatten` can't be combined with `rename` or `default`",
+            ));
+        }
+        return Ok(Source::Flatten);
+    }
+
+    let name = match rename {
+        Some(name) => name,
+        // named fields are checked by the caller
+        None => field.ident.as_ref().unwrap().to_string(),
+    };
+    Ok(Source::Column { name, default })
+}
+
+pub fn expand(input: DeriveInput) -> syn::Result<TokenStream> {
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => {
+                return Err(syn::Error::new_spanned(
+                    &input.ident,
+                    "`FromRow` can only be derived for structs with named fields",
+                ))
+            }
+        },
+        _ => {
+            return Err(syn::Error::new_spanned(
+                &input.ident,
+                "`FromRow` can only be derived for structs",
+            ))
+        }
+    };
+
+    let values = fields
+        .iter()
+        .map(|field| {
+            let ident = &field.ident;
+            let ty = &field.ty;
+            let value = match field_source(field)? {
+                Source::Column {
+                    name,
+                    default: false,
+                } => quote!(row.try_get::<_, #ty>(#name)?),
+                Source::Column {
+                    name,
+                    default: true,
+                } => quote! {
+                    if ::abstract_db_access::pg::has_column(row, #name) {
+                        row.try_get::<_, #ty>(#name)?
+                    } else {
+                        ::core::default::Default::default()
+                    }
+                },
+                Source::Flatten => {
+                    quote!(<#ty as ::abstract_db_access::pg::FromRow>::from_row(row)?)
+                }
+            };
+            Ok(quote!(#ident: #value))
+        })
+        .collect::<syn::Result<Vec<_>>>()?;
+
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    Ok(quote! {
+        impl #impl_generics ::abstract_db_access::pg::FromRow for #name #ty_generics #where_clause {
+            fn from_row(
+                row: &::abstract_db_access::pg::Row,
+            ) -> ::core::result::Result<Self, ::abstract_db_access::RepositoryError> {
+                ::core::result::Result::Ok(Self {
+                    #(#values,)*
+                })
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use quote::ToTokens;
+    use syn::{
+        parse_quote,
+        visit_mut::{self, VisitMut},
+        ExprStruct, ItemImpl,
+    };
+
+    use super::*;
+
+    /// Values of the fields of the built struct, as token strings
+    #[derive(Default)]
+    struct FieldValues(Vec<(String, String)>);
+
+    impl VisitMut for FieldValues {
+        fn visit_expr_struct_mut(&mut self, expr: &mut ExprStruct) {
+            for field in &expr.fields {
+                self.0.push((
+                    field.member.to_token_stream().to_string(),
+                    field.expr.to_token_stream().to_string(),
+                ));
+            }
+            visit_mut::visit_expr_struct_mut(self, expr);
+        }
+    }
+
+    fn field_values(input: DeriveInput) -> Vec<(String, String)> {
+        let mut item: ItemImpl = syn::parse2(expand(input).unwrap()).unwrap();
+        let mut values = FieldValues::default();
+        values.visit_item_impl_mut(&mut item);
+        values.0
+    }
+
+    fn value(expr: TokenStream) -> String {
+        syn::parse2::<syn::Expr>(expr)
+            .unwrap()
+            .to_token_stream()
+            .to_string()
+    }
+
+    #[test]
+    fn reads_the_columns_of_the_fields() {
+        let values = field_values(parse_quote! {
+            struct User {
+                id: Uuid,
+                email: Option<String>,
+            }
+        });
+
+        assert_eq!(
+            values,
+            [
+                ("id".into(), value(quote!(row.try_get::<_, Uuid>("id")?))),
+                (
+                    "email".into(),
+                    value(quote!(row.try_get::<_, Option<String>>("email")?))
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn reads_the_renamed_and_default_columns() {
+        let values = field_values(parse_quote! {
+            struct User {
+                #[row(rename = "user_name")]
+                name: String,
+                #[row(default, rename = "score")]
+                points: i64,
+            }
+        });
+
+        assert_eq!(
+            values[0].1,
+            value(quote!(row.try_get::<_, String>("user_name")?))
+        );
+        assert_eq!(
+            values[1].1,
+            value(quote! {
+                if ::abstract_db_access::pg::has_column(row, "score") {
+                    row.try_get::<_, i64>("score")?
+                } else {
+                    ::core::default::Default::default()
+                }
+            })
+        );
+    }
+
+    #[test]
+    fn builds_the_flattened_fields_from_the_row() {
+        let values = field_values(parse_quote! {
+            struct Order {
+                id: i64,
+                #[row(flatten)]
+                customer: Customer,
+            }
+        });
+
+        assert_eq!(
+            values[1],
+            (
+                "customer".into(),
+                value(quote!(
+                    <Customer as ::abstract_db_access::pg::FromRow>::from_row(row)?
+                ))
+            )
+        );
+    }
+
+    #[test]
+    fn keeps_the_generics_of_the_struct() {
+        let item: ItemImpl = syn::parse2(
+            expand(parse_quote! {
+                struct Page<T: FromRow> {
+                    #[row(flatten)]
+                    item: T,
+                }
+            })
+            .unwrap(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            item.self_ty.to_token_stream().to_string(),
+            quote!(Page<T>).to_string()
+        );
+        assert_eq!(item.generics.type_params().count(), 1);
+    }
+
+    fn error(input: DeriveInput) -> String {
+        expand(input).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn rejects_tuple_structs_and_enums() {
+        assert_eq!(
+            error(parse_quote!(
+                struct Id(i64);
+            )),
+            "`FromRow` can only be derived for structs with named fields"
+        );
+        assert_eq!(
+            error(parse_quote!(
+                enum State {
+                    Active,
+                }
+            )),
+            "`FromRow` can only be derived for structs"
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_attributes() {
+        assert!(error(parse_quote! {
+            struct User {
+                #[row(flatten, default)]
+                profile: Profile,
+            }
+        })
+        .contains("`flatten` can't be combined"));
+        assert!(error(parse_quote! {
+            struct User {
+                #[row(skip)]
+                name: String,
+            }
+        })
+        .starts_with("unknown attribute"));
+    }
+}
@@ -0,0 +1,58 @@
+use proc_macro::TokenStream;
+use syn::{parse_macro_input, DeriveInput, ItemImpl};
+
+mod from_row;
+mod repository;
+
+/// Implements a repository for the unit and transaction types of the chosen backends.
+///
+/// The impl block is written once for a generic executor, its type parameter being replaced by
+/// each concrete type. The bounds of the executor are dropped, the body is checked against the
+/// concrete types instead, so it can use any method they share, e.g. `self.pg_client()` of
+/// `PgConnection` in both units and transactions.
+///
+/// Backends:
+/// - `pg_deadpool`: `pg_deadpool::PgUnit` and `pg_deadpool::PgTrxUnit<'_>`
+/// - `pg_tokio`: `pg_tokio::PgUnit` and `pg_tokio::PgTrxUnit<'_>`
+/// - `pg_tokio_lazy`: `pg_tokio::lazy::LazyUnit` and `pg_tokio::lazy::LazyTrxUnit<'_>`
+/// - `sqlx(DB)`: `sqlx::SqlxUnit<DB>` and `sqlx::SqlxTrxUnit<'_, DB>`
+///
+/// ```ignore
+/// #[repository(for = [pg_deadpool, pg_tokio])]
+/// #[async_trait]
+/// impl<U: PgConnection + Send + Sync> UserRepository for U {
+///     async fn find(&self, id: Uuid) -> Result<Option<User>, RepositoryError> {
+///         let row = self.pg_client().query_opt(FIND_USER, &[&id]).await?;
+///         Ok(row.map(User::from))
+///     }
+/// }
+/// ```
+///
+/// Attributes placed after it, like `#[async_trait]`, are applied to every generated impl.
+#[proc_macro_attribute]
+pub fn repository(args: TokenStream, input: TokenStream) -> TokenStream {
+    let args = parse_macro_input!(args as repository::Args);
+    let item = parse_macro_input!(input as ItemImpl);
+
+    repository::expand(args, item)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// Derives `pg::FromRow`, reading each field from the column of the same name.
+///
+/// Field attributes:
+/// - `#[row(rename = "column")]`: reads the field from `column`
+/// - `#[row(default)]`: uses `Default::default()` when the column is not selected
+/// - `#[row(flatten)]`: builds the field from the same row through its own `FromRow`
+///
+/// `Option` fields are `None` for NULL values, any other conversion error is returned as a
+/// `RepositoryError` instead of panicking.
+#[proc_macro_derive(FromRow, attributes(row))]
+pub fn derive_from_row(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    from_row::expand(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
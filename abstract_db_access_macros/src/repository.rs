@@ -0,0 +1,311 @@
+use proc_macro2::{Span, TokenStream};
+use quote::quote;
+use syn::{
+    bracketed, parenthesized,
+    parse::{Parse, ParseStream},
+    parse_quote,
+    punctuated::Punctuated,
+    visit_mut::{self, VisitMut},
+    GenericParam, Ident, ItemImpl, Lifetime, Token, Type, WherePredicate,
+};
+
+/// Lifetime of the generated transaction impls
+const TRX_LIFETIME: &str = "'__trx";
+
+/// Backend whose unit and transaction types get the repository
+enum Backend {
+    PgDeadpool,
+    PgTokio,
+    PgTokioLazy,
+    Sqlx(Type),
+}
+
+impl Parse for Backend {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let name: Ident = input.parse()?;
+        match name.to_string().as_str() {
+            "pg_deadpool" => Ok(Backend::PgDeadpool),
+            "pg_tokio" => Ok(Backend::PgTokio),
+            "pg_tokio_lazy" => Ok(Backend::PgTokioLazy),
+            "sqlx" => {
+                if !input.peek(syn::token::Paren) {
+                    return Err(syn::Error::new(
+                        name.span(),
+                        "expected the database of the sqlx backend, e.g. `sqlx(sqlx::Postgres)`",
+                    ));
+                }
+                let content;
+                parenthesized!(content in input);
+                Ok(Backend::Sqlx(content.parse()?))
+            }
+            _ => Err(syn::Error::new(
+                name.span(),
+                "unknown backend, expected `pg_deadpool`, `pg_tokio`, `pg_tokio_lazy` or `sqlx(<database>)`",
+            )),
+        }
+    }
+}
+
+impl Backend {
+    /// Unit and transaction types of the backend
+    fn types(&self, trx: &Lifetime) -> [Type; 2] {
+        match self {
+            Backend::PgDeadpool => [
+                parse_quote!(::abstract_db_access::pg_deadpool::PgUnit),
+                parse_quote!(::abstract_db_access::pg_deadpool::PgTrxUnit<#trx>),
+            ],
+            Backend::PgTokio => [
+                parse_quote!(::abstract_db_access::pg_tokio::PgUnit),
+                parse_quote!(::abstract_db_access::pg_tokio::PgTrxUnit<#trx>),
+            ],
+            Backend::PgTokioLazy => [
+                parse_quote!(::abstract_db_access::pg_tokio::lazy::LazyUnit),
+                parse_quote!(::abstract_db_access::pg_tokio::lazy::LazyTrxUnit<#trx>),
+            ],
+            Backend::Sqlx(db) => [
+                parse_quote!(::abstract_db_access::sqlx::SqlxUnit<#db>),
+                parse_quote!(::abstract_db_access::sqlx::SqlxTrxUnit<#trx, #db>),
+            ],
+        }
+    }
+}
+
+/// Arguments of the attribute, `for = [<backend>, ...]`
+pub struct Args {
+    backends: Punctuated<Backend, Token![,]>,
+}
+
+impl Parse for Args {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        input.parse::<Token![for]>()?;
+        input.parse::<Token![=]>()?;
+        let content;
+        bracketed!(content in input);
+        let backends = content.parse_terminated(Backend::parse)?;
+
+        if backends.is_empty() {
+            return Err(input.error("expected at least one backend"));
+        }
+
+        Ok(Self { backends })
+    }
+}
+
+/// Replaces the executor type parameter by a concrete type
+struct ReplaceExecutor<'a> {
+    executor: &'a Ident,
+    ty: &'a Type,
+}
+
+impl<'a> VisitMut for ReplaceExecutor<'a> {
+    fn visit_type_mut(&mut self, ty: &mut Type) {
+        if let Type::Path(path) = ty {
+            if path.qself.is_none() && path.path.is_ident(self.executor) {
+                *ty = self.ty.clone();
+                return;
+            }
+        }
+        visit_mut::visit_type_mut(self, ty);
+    }
+}
+
+/// Type parameter the impl block is written for
+fn executor_param(item: &ItemImpl) -> syn::Result<Ident> {
+    let error = || {
+        syn::Error::new_spanned(
+            &item.self_ty,
+            "expected a type parameter of the impl, e.g. `impl<U: PgConnection> Repository for U`",
+        )
+    };
+
+    let ident = match &*item.self_ty {
+        Type::Path(path) if path.qself.is_none() => path.path.get_ident().ok_or_else(error)?,
+        _ => return Err(error()),
+    };
+
+    let is_param = item
+        .generics
+        .type_params()
+        .any(|param| &param.ident == ident);
+    if !is_param {
+        return Err(error());
+    }
+
+    Ok(ident.clone())
+}
+
+pub fn expand(args: Args, mut item: ItemImpl) -> syn::Result<TokenStream> {
+    let executor = executor_param(&item)?;
+
+    // the bounds of the executor are dropped, the concrete types are checked by the impl body
+    item.generics.params = item
+        .generics
+        .params
+        .into_iter()
+        .filter(|param| !matches!(param, GenericParam::Type(param) if param.ident == executor))
+        .collect();
+    if let Some(where_clause) = &mut item.generics.where_clause {
+        where_clause.predicates = where_clause
+            .predicates
+            .clone()
+            .into_iter()
+            .filter(|predicate| match predicate {
+                WherePredicate::Type(predicate) => {
+                    !matches!(&predicate.bounded_ty, Type::Path(path) if path.path.is_ident(&executor))
+                }
+                _ => true,
+            })
+            .collect();
+    }
+
+    let trx = Lifetime::new(TRX_LIFETIME, Span::call_site());
+    let impls = args.backends.iter().flat_map(|backend| {
+        let [unit, transaction] = backend.types(&trx);
+
+        let mut unit_impl = item.clone();
+        ReplaceExecutor {
+            executor: &executor,
+            ty: &unit,
+        }
+        .visit_item_impl_mut(&mut unit_impl);
+
+        let mut trx_impl = item.clone();
+        trx_impl.generics.params.insert(0, parse_quote!(#trx));
+        ReplaceExecutor {
+            executor: &executor,
+            ty: &transaction,
+        }
+        .visit_item_impl_mut(&mut trx_impl);
+
+        [unit_impl, trx_impl]
+    });
+
+    Ok(quote!(#(#impls)*))
+}
+
+#[cfg(test)]
+mod tests {
+    use quote::ToTokens;
+    use syn::{File, Item};
+
+    use super::*;
+
+    fn expand_impls(args: TokenStream, item: ItemImpl) -> syn::Result<Vec<ItemImpl>> {
+        let file: File = syn::parse2(expand(syn::parse2(args)?, item)?)?;
+        Ok(file
+            .items
+            .into_iter()
+            .map(|item| match item {
+                Item::Impl(item) => item,
+                _ => panic!("expected impl blocks"),
+            })
+            .collect())
+    }
+
+    fn self_types(impls: &[ItemImpl]) -> Vec<String> {
+        impls
+            .iter()
+            .map(|item| item.self_ty.to_token_stream().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn implements_the_unit_and_transaction_of_each_backend() {
+        let impls = expand_impls(
+            quote!(for = [pg_tokio, pg_tokio_lazy, sqlx(sqlx::Postgres)]),
+            parse_quote! {
+                impl<U: PgConnection + Send + Sync> UserRepository for U {}
+            },
+        )
+        .unwrap();
+
+        let expected: Vec<Type> = vec![
+            parse_quote!(::abstract_db_access::pg_tokio::PgUnit),
+            parse_quote!(::abstract_db_access::pg_tokio::PgTrxUnit<'__trx>),
+            parse_quote!(::abstract_db_access::pg_tokio::lazy::LazyUnit),
+            parse_quote!(::abstract_db_access::pg_tokio::lazy::LazyTrxUnit<'__trx>),
+            parse_quote!(::abstract_db_access::sqlx::SqlxUnit<sqlx::Postgres>),
+            parse_quote!(::abstract_db_access::sqlx::SqlxTrxUnit<'__trx, sqlx::Postgres>),
+        ];
+        let expected: Vec<_> = expected
+            .iter()
+            .map(|ty| ty.to_token_stream().to_string())
+            .collect();
+        assert_eq!(self_types(&impls), expected);
+
+        // only the transaction impls are generic over its lifetime
+        let lifetimes: Vec<_> = impls
+            .iter()
+            .map(|item| item.generics.lifetimes().count())
+            .collect();
+        assert_eq!(lifetimes, [0, 1, 0, 1, 0, 1]);
+    }
+
+    #[test]
+    fn drops_the_bounds_of_the_executor_only() {
+        let impls = expand_impls(
+            quote!(for = [pg_deadpool]),
+            parse_quote! {
+                impl<T: Send, U> Repository<T> for U where U: PgConnection, T: Sync {}
+            },
+        )
+        .unwrap();
+
+        for item in &impls {
+            let params: Vec<_> = item
+                .generics
+                .type_params()
+                .map(|param| &param.ident)
+                .collect();
+            assert_eq!(params, ["T"]);
+
+            let where_clause = item.generics.where_clause.as_ref().unwrap();
+            assert_eq!(
+                where_clause.to_token_stream().to_string(),
+                quote!(where T: Sync).to_string()
+            );
+        }
+    }
+
+    #[test]
+    fn replaces_the_executor_in_the_body() {
+        let impls = expand_impls(
+            quote!(for = [pg_tokio]),
+            parse_quote! {
+                impl<U: PgConnection> Repository for U {
+                    fn unit(&self) -> &U { self }
+                }
+            },
+        )
+        .unwrap();
+
+        let body = impls[0].items[0].to_token_stream().to_string();
+        assert!(body.contains(&quote!(-> &::abstract_db_access::pg_tokio::PgUnit).to_string()));
+    }
+
+    #[test]
+    fn rejects_invalid_arguments() {
+        for args in [
+            quote!(for = []),
+            quote!(for = [pg_unknown]),
+            quote!(for = [sqlx]),
+            quote!(backends = [pg_tokio]),
+        ] {
+            assert!(syn::parse2::<Args>(args.clone()).is_err(), "{args}");
+        }
+    }
+
+    #[test]
+    fn rejects_impls_for_concrete_types() {
+        let err = expand_impls(
+            quote!(for = [pg_tokio]),
+            parse_quote!(impl UserRepository for PgUnit {}),
+        )
+        .err()
+        .unwrap();
+
+        assert!(err
+            .to_string()
+            .contains("expected a type parameter of the impl"));
+    }
+}
@@ -0,0 +1,10 @@
+/// Checks the misuses of units and transactions are rejected by the compiler.
+///
+/// The expected errors are in the `.stderr` files next to each case, regenerated with
+/// `TRYBUILD=overwrite`.
+#[test]
+fn compile_fail() {
+    let cases = trybuild::TestCases::new();
+    cases.pass("tests/ui/pass/*.rs");
+    cases.compile_fail("tests/ui/fail/*.rs");
+}
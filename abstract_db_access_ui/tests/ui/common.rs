@@ -0,0 +1,50 @@
+use abstract_db_access::{
+    pg::{FromRow, PgEntity},
+    repository, AutoCommit, Entity, InTransaction, Repository, RepositoryError,
+};
+use async_trait::async_trait;
+use tokio_postgres::types::ToSql;
+
+#[derive(FromRow)]
+pub struct Account {
+    pub id: i64,
+    pub balance: i64,
+}
+
+impl Entity for Account {
+    type Key = i64;
+
+    const TABLE: &'static str = "public.account";
+    const KEY: &'static [&'static str] = &["id"];
+    const COLUMNS: &'static [&'static str] = &["id", "balance"];
+}
+
+impl PgEntity for Account {
+    fn key_values(key: &i64) -> Vec<&(dyn ToSql + Sync)> {
+        vec![key]
+    }
+
+    fn values(&self) -> Vec<&(dyn ToSql + Sync)> {
+        vec![&self.id, &self.balance]
+    }
+}
+
+#[repository(for = [pg_deadpool])]
+#[async_trait]
+impl<U> Repository<Account> for U {}
+
+/// Moves the balance between accounts, the two updates must be atomic
+pub async fn transfer<T>(trx: &mut T, from: &mut Account, to: &mut Account, amount: i64)
+where
+    T: Repository<Account> + InTransaction,
+{
+    from.balance -= amount;
+    to.balance += amount;
+    let _ = Repository::update(trx, from).await;
+    let _ = Repository::update(trx, to).await;
+}
+
+/// Refreshes a materialized view, visible to other connections once it returns
+pub async fn refresh_report<U: AutoCommit>(_unit: &mut U) -> Result<(), RepositoryError> {
+    Ok(())
+}
@@ -0,0 +1,13 @@
+use abstract_db_access::{pg_deadpool::PgUnit, DbUnit};
+
+#[path = "../common.rs"]
+mod common;
+
+use common::refresh_report;
+
+async fn run(mut unit: PgUnit) {
+    let mut trx = unit.transaction().await.unwrap();
+    refresh_report(&mut trx).await.unwrap();
+}
+
+fn main() {}
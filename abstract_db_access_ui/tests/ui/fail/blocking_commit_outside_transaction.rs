@@ -0,0 +1,7 @@
+use abstract_db_access::{blocking::TransactionUnit, sqlite::SqliteUnit};
+
+fn run(unit: SqliteUnit) {
+    TransactionUnit::commit(unit).unwrap();
+}
+
+fn main() {}
@@ -0,0 +1,8 @@
+use abstract_db_access::{blocking::DbUnit, sqlite::SqliteUnit};
+
+fn run(mut unit: SqliteUnit) {
+    let mut trx = DbUnit::transaction(&mut unit).unwrap();
+    let _nested = DbUnit::transaction(&mut trx).unwrap();
+}
+
+fn main() {}
@@ -0,0 +1,7 @@
+use abstract_db_access::{pg_deadpool::PgUnit, TransactionUnit};
+
+async fn run(unit: PgUnit) {
+    TransactionUnit::commit(unit).await.unwrap();
+}
+
+fn main() {}
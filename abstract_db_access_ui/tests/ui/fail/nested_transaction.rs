@@ -0,0 +1,8 @@
+use abstract_db_access::{pg_deadpool::PgUnit, DbUnit};
+
+async fn run(mut unit: PgUnit) {
+    let mut trx = unit.transaction().await.unwrap();
+    let _nested = DbUnit::transaction(&mut trx).await.unwrap();
+}
+
+fn main() {}
@@ -0,0 +1,17 @@
+use abstract_db_access::{
+    pg::{RowLock, TransactionRowLock},
+    pg_deadpool::PgUnit,
+};
+
+#[path = "../common.rs"]
+mod common;
+
+use common::Account;
+
+async fn run(unit: PgUnit) {
+    let _account = TransactionRowLock::<Account>::find_by_id_for_update(&unit, &1, RowLock::NoWait)
+        .await
+        .unwrap();
+}
+
+fn main() {}
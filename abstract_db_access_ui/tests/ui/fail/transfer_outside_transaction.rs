@@ -0,0 +1,12 @@
+use abstract_db_access::pg_deadpool::PgUnit;
+
+#[path = "../common.rs"]
+mod common;
+
+use common::{transfer, Account};
+
+async fn run(mut unit: PgUnit, mut from: Account, mut to: Account) {
+    transfer(&mut unit, &mut from, &mut to, 10).await;
+}
+
+fn main() {}
@@ -0,0 +1,23 @@
+use abstract_db_access::{
+    pg::{RowLock, TransactionRowLock},
+    pg_deadpool::PgUnit,
+    DbUnit, TransactionUnit,
+};
+
+#[path = "../common.rs"]
+mod common;
+
+use common::{refresh_report, transfer, Account};
+
+#[allow(dead_code)]
+async fn run(mut unit: PgUnit) {
+    let mut trx = unit.transaction().await.unwrap();
+    let mut from: Account = trx.find_by_id_for_update(&1, RowLock::NoWait).await.unwrap().unwrap();
+    let mut to: Account = trx.find_by_id_for_update(&2, RowLock::Wait).await.unwrap().unwrap();
+    transfer(&mut trx, &mut from, &mut to, 10).await;
+    trx.commit().await.unwrap();
+
+    refresh_report(&mut unit).await.unwrap();
+}
+
+fn main() {}
@@ -6,6 +6,26 @@ export $(grep -v '^#' .env | xargs);
 
 cargo test --tests;
 
-cargo run --example pg_deadpool --features=pg_deadpool;
+cargo test -p abstract_db_access_ui;
 
-cargo run --example sqlx --features=sqlx;
+cargo run --example pg_deadpool --features=pg_deadpool,macros;
+
+cargo run --example pg_tokio --features=pg_tokio,macros;
+
+cargo run --example sqlx --features=sqlx,macros;
+
+cargo run --example pg_blocking --features=pg_blocking,r2d2_pool;
+
+cargo run --example sqlite --features=sqlite;
+
+cargo run --example mock --features=mock;
+
+cargo run --example fault --features=mock,fault;
+
+cargo run --example testing --features=pg_deadpool,testing;
+
+cargo run --example test_database --features=pg_deadpool;
+
+cargo run --example migrate --features=pg_deadpool,migrate;
+
+cargo +stable run --example native_async --features=mock,native_async;
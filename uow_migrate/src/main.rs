@@ -0,0 +1,313 @@
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process::ExitCode,
+    time::Duration,
+};
+
+use abstract_db_access::{
+    migrate::{self, MigrationState, Migrator},
+    pg_tokio::PgUnit,
+};
+use tokio_postgres_rustls::MakeRustlsConnect;
+
+const USAGE: &str = "\
+Usage: uow-migrate [--dir <path>] <command>
+
+Commands:
+    up            Applies the pending migrations
+    down <n>      Reverts the last <n> applied migrations
+    status        Lists the migrations and their state
+    new <name>    Creates the files of a new migration
+    verify        Checks the applied migrations against their files
+
+Options:
+    --dir <path>  Directory of the migrations, defaults to $MIGRATIONS_DIR or `migrations`
+
+The database is read from $DATABASE_URL, or from $DATABASE_HOST, $DATABASE_PORT,
+$DATABASE_NAME, $DATABASE_USER and $DATABASE_PASSWORD.";
+
+#[derive(Debug)]
+enum Command {
+    Up,
+    Down(usize),
+    Status,
+    New(String),
+    Verify,
+}
+
+#[derive(Debug)]
+struct Args {
+    command: Command,
+    dir: PathBuf,
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Args, String> {
+    let mut dir = env::var("MIGRATIONS_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| PathBuf::from("migrations"));
+    let mut command = None;
+
+    while let Some(arg) = args.next() {
+        let parsed = match arg.as_str() {
+            "--dir" => {
+                dir = args.next().ok_or("missing --dir path")?.into();
+                continue;
+            }
+            "up" => Command::Up,
+            "down" => {
+                let steps = args
+                    .next()
+                    .ok_or("missing number of migrations to revert")?;
+                Command::Down(
+                    steps
+                        .parse()
+                        .map_err(|_| format!("invalid number of migrations `{steps}`"))?,
+                )
+            }
+            "status" => Command::Status,
+            "new" => Command::New(args.next().ok_or("missing migration name")?),
+            "verify" => Command::Verify,
+            arg => return Err(format!("unknown argument `{arg}`")),
+        };
+
+        if command.replace(parsed).is_some() {
+            return Err("only one command can be given".into());
+        }
+    }
+
+    Ok(Args {
+        command: command.ok_or("missing command")?,
+        dir,
+    })
+}
+
+macro_rules! get_env {
+    ($env:literal) => {
+        env::var($env).map_err(|_| concat!("Missing env var ", $env))
+    };
+}
+
+fn connection_config() -> Result<tokio_postgres::Config, String> {
+    if let Ok(url) = env::var("DATABASE_URL") {
+        return url
+            .parse()
+            .map_err(|err| format!("invalid DATABASE_URL: {err}"));
+    }
+
+    let port: u16 = get_env!("DATABASE_PORT")?
+        .parse()
+        .map_err(|_| "Invalid DATABASE_PORT")?;
+
+    let mut cfg = tokio_postgres::Config::new();
+    cfg.host(&get_env!("DATABASE_HOST")?);
+    cfg.port(port);
+    cfg.dbname(&get_env!("DATABASE_NAME")?);
+    cfg.user(&get_env!("DATABASE_USER")?);
+    cfg.password(get_env!("DATABASE_PASSWORD")?);
+    cfg.connect_timeout(Duration::from_millis(5000));
+    cfg.ssl_mode(tokio_postgres::config::SslMode::Prefer);
+    Ok(cfg)
+}
+
+fn tls_config() -> MakeRustlsConnect {
+    let mut root_store = rustls::RootCertStore::empty();
+    root_store.add_server_trust_anchors(webpki_roots::TLS_SERVER_ROOTS.0.iter().map(|ta| {
+        rustls::OwnedTrustAnchor::from_subject_spki_name_constraints(
+            ta.subject,
+            ta.spki,
+            ta.name_constraints,
+        )
+    }));
+
+    let tls_config = rustls::ClientConfig::builder()
+        .with_safe_defaults()
+        .with_root_certificates(root_store)
+        .with_no_client_auth();
+
+    MakeRustlsConnect::new(tls_config)
+}
+
+async fn connect() -> Result<PgUnit, String> {
+    let mut config = connection_config()?;
+    config.application_name("uow-migrate");
+
+    let (client, connection) = config
+        .connect(tls_config())
+        .await
+        .map_err(|err| format!("failed to connect: {err}"))?;
+
+    tokio::spawn(async move {
+        if let Err(err) = connection.await {
+            eprintln!("connection error: {err}");
+        }
+    });
+
+    Ok(PgUnit::new(client))
+}
+
+fn load_migrator(dir: &Path) -> Result<Migrator, String> {
+    migrate::load_dir(dir)
+        .and_then(Migrator::new)
+        .map_err(|err| {
+            format!(
+                "failed to load the migrations of {}: {err:?}",
+                dir.display()
+            )
+        })
+}
+
+/// Creates the files of the migration following the last one of the `dir`.
+fn new_migration(dir: &Path, name: &str) -> Result<(), String> {
+    let valid_name = !name.is_empty()
+        && name
+            .chars()
+            .all(|ch| ch.is_ascii_lowercase() || ch.is_ascii_digit() || ch == '_');
+    if !valid_name {
+        return Err(format!(
+            "invalid migration name `{name}`, only lowercase letters, digits and underscores are allowed"
+        ));
+    }
+
+    fs::create_dir_all(dir).map_err(|err| format!("failed to create {}: {err}", dir.display()))?;
+    let version = load_migrator(dir)?
+        .migrations()
+        .last()
+        .map_or(1, |last| last.version() + 1);
+
+    for (suffix, script) in [
+        ("up", "-- Applies the migration\n"),
+        ("down", "-- Reverts the migration\n"),
+    ] {
+        let path = dir.join(format!("{version:04}_{name}.{suffix}.sql"));
+        fs::write(&path, script)
+            .map_err(|err| format!("failed to write {}: {err}", path.display()))?;
+        println!("created {}", path.display());
+    }
+
+    Ok(())
+}
+
+async fn run(args: Args) -> Result<(), String> {
+    if let Command::New(name) = &args.command {
+        return new_migration(&args.dir, name);
+    }
+
+    let migrator = load_migrator(&args.dir)?;
+    let mut unit = connect().await?;
+
+    match args.command {
+        Command::Up => {
+            let applied = migrator
+                .up(&mut unit)
+                .await
+                .map_err(|err| format!("failed to apply the migrations: {err:?}"))?;
+            println!("applied {} migration(s) {applied:?}", applied.len());
+        }
+        Command::Down(steps) => {
+            let reverted = migrator
+                .down(&mut unit, steps)
+                .await
+                .map_err(|err| format!("failed to revert the migrations: {err:?}"))?;
+            println!("reverted {} migration(s) {reverted:?}", reverted.len());
+        }
+        Command::Status => {
+            let status = migrator
+                .status(&mut unit)
+                .await
+                .map_err(|err| format!("failed to read the migrations: {err:?}"))?;
+            for migration in status {
+                let state = match migration.state {
+                    MigrationState::Pending => "pending",
+                    MigrationState::Applied => "applied",
+                    MigrationState::Modified => "modified",
+                    MigrationState::Unknown => "unknown",
+                };
+                println!("{:>6}  {state:<8}  {}", migration.version, migration.name);
+            }
+        }
+        Command::Verify => {
+            migrator
+                .verify(&mut unit)
+                .await
+                .map_err(|err| format!("verification failed: {err:?}"))?;
+            println!("the applied migrations match their files");
+        }
+        Command::New(_) => unreachable!("handled without connecting"),
+    }
+
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let args = match parse_args(env::args().skip(1)) {
+        Ok(args) => args,
+        Err(err) => {
+            eprintln!("error: {err}\n\n{USAGE}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    match run(args).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("error: {err}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Args, String> {
+        parse_args(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn parses_the_commands() {
+        assert!(matches!(parse(&["up"]).unwrap().command, Command::Up));
+        assert!(matches!(
+            parse(&["down", "2"]).unwrap().command,
+            Command::Down(2)
+        ));
+        assert!(matches!(
+            parse(&["status"]).unwrap().command,
+            Command::Status
+        ));
+        assert!(matches!(
+            parse(&["verify"]).unwrap().command,
+            Command::Verify
+        ));
+        assert!(matches!(
+            parse(&["new", "add_users"]).unwrap().command,
+            Command::New(name) if name == "add_users"
+        ));
+    }
+
+    #[test]
+    fn parses_the_directory_around_the_command() {
+        let args = parse(&["--dir", "db/migrations", "up"]).unwrap();
+        assert_eq!(args.dir, PathBuf::from("db/migrations"));
+
+        let args = parse(&["status", "--dir", "db/migrations"]).unwrap();
+        assert_eq!(args.dir, PathBuf::from("db/migrations"));
+    }
+
+    #[test]
+    fn rejects_invalid_arguments() {
+        for args in [
+            &[][..],
+            &["up", "status"],
+            &["down"],
+            &["down", "-1"],
+            &["new"],
+            &["up", "--dir"],
+            &["apply"],
+        ] {
+            assert!(parse(args).is_err(), "{args:?}");
+        }
+    }
+}
@@ -30,18 +30,51 @@ pub mod env_var {
             .parse()
             .expect("Invalid DATABASE_PORT");
 
-        let database_url = format!("postgres://{database_user}:{database_password}@{database_host}:{database_port}/{database_name}");
-
-        EnvVar {
+        let mut env_var = EnvVar {
             database_host,
             database_name,
             database_password,
             database_port,
             database_user,
-            database_url,
+            database_url: String::new(),
+        };
+        env_var.database_url = env_var.database_url_for(&env_var.database_name);
+        env_var
+    }
+
+    impl EnvVar {
+        /// URL of another database of the same server
+        pub fn database_url_for(&self, database_name: &str) -> String {
+            let EnvVar {
+                database_user,
+                database_password,
+                database_host,
+                database_port,
+                ..
+            } = self;
+            format!(
+                "postgres://{}:{}@{database_host}:{database_port}/{}",
+                encode(database_user),
+                encode(database_password),
+                encode(database_name)
+            )
         }
     }
 
+    /// Percent-encodes a component of the URL, keeping only the unreserved characters
+    fn encode(component: &str) -> String {
+        let mut encoded = String::with_capacity(component.len());
+        for byte in component.bytes() {
+            match byte {
+                b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                    encoded.push(byte as char)
+                }
+                _ => encoded.push_str(&format!("%{byte:02X}")),
+            }
+        }
+        encoded
+    }
+
     pub fn get() -> &'static EnvVar {
         &ENV_VAR
     }
@@ -56,17 +89,17 @@ pub mod connection {
 
     use super::env_var;
 
-    fn connection_config() -> tokio_postgres::Config {
+    fn connection_config(database_name: &str) -> tokio_postgres::Config {
         let env = env_var::get();
 
         let mut cfg = tokio_postgres::Config::new();
-        cfg.dbname(&env.database_name);
+        cfg.dbname(database_name);
         cfg.user(&env.database_user);
         cfg.password(env.database_password.clone());
         cfg.port(env.database_port);
         cfg.host(&env.database_host);
         cfg.connect_timeout(Duration::from_millis(5000));
-        cfg.application_name("UoW test".into());
+        cfg.application_name("UoW test");
         cfg.ssl_mode(tokio_postgres::config::SslMode::Prefer);
         cfg
     }
@@ -98,7 +131,7 @@ pub mod connection {
         config.password = cfg
             .get_password()
             .map(|pass| String::from_utf8(pass.into()).unwrap());
-        config.port = cfg.get_ports().iter().nth(0).copied();
+        config.port = cfg.get_ports().iter().next().copied();
         config.host = Some(env.database_host.clone());
         config.connect_timeout = cfg.get_connect_timeout().cloned();
         config.application_name = Some("UoW test".into());
@@ -110,7 +143,12 @@ pub mod connection {
     }
 
     pub fn create_pg_deadpool() -> deadpool_postgres::Pool {
-        let config = connection_config();
+        create_pg_deadpool_for(&env_var::get().database_name)
+    }
+
+    /// Pool of connections to another database of the same server
+    pub fn create_pg_deadpool_for(database_name: &str) -> deadpool_postgres::Pool {
+        let config = connection_config(database_name);
         let tls = tls_config();
 
         deadpool_config(config)
@@ -118,10 +156,34 @@ pub mod connection {
             .unwrap()
     }
 
+    /// Pool of at most `max_size` connections
+    pub fn create_pg_deadpool_sized(max_size: usize) -> deadpool_postgres::Pool {
+        let mut config = deadpool_config(connection_config(&env_var::get().database_name));
+        config.pool = Some(deadpool_postgres::PoolConfig::new(max_size));
+
+        config
+            .create_pool(Some(deadpool_postgres::Runtime::Tokio1), tls_config())
+            .unwrap()
+    }
+
+    /// Client of a single connection, driven by a spawned task
+    pub async fn create_pg_client() -> tokio_postgres::Client {
+        let config = connection_config(&env_var::get().database_name);
+        let (client, connection) = config.connect(tls_config()).await.unwrap();
+
+        tokio::spawn(async move {
+            if let Err(err) = connection.await {
+                eprintln!("connection error: {err}");
+            }
+        });
+
+        client
+    }
+
     pub type PgBb8pool = bb8::Pool<PostgresConnectionManager<MakeRustlsConnect>>;
 
     pub async fn create_pg_bb8pool() -> PgBb8pool {
-        let config = connection_config();
+        let config = connection_config(&env_var::get().database_name);
         let tls = tls_config();
         let manager = PostgresConnectionManager::new(config, tls);
 
@@ -133,7 +195,12 @@ pub mod connection {
     }
 
     pub async fn create_sqlx_pool() -> sqlx::PgPool {
-        let dburl = env_var::get().database_url.clone();
+        create_sqlx_pool_for(&env_var::get().database_name).await
+    }
+
+    /// Pool of connections to another database of the same server
+    pub async fn create_sqlx_pool_for(database_name: &str) -> sqlx::PgPool {
+        let dburl = env_var::get().database_url_for(database_name);
         sqlx::postgres::PgPoolOptions::new()
             .max_connections(2)
             .acquire_timeout(Duration::from_millis(1000))
@@ -144,3 +211,160 @@ pub mod connection {
             .unwrap()
     }
 }
+
+pub mod test_database {
+    use std::{
+        collections::HashSet,
+        future::Future,
+        panic::{self, AssertUnwindSafe},
+        sync::Mutex,
+    };
+
+    use futures_util::FutureExt;
+    use lazy_static::lazy_static;
+
+    use super::connection;
+
+    lazy_static! {
+        /// Templates already built by this process
+        static ref TEMPLATES: Mutex<HashSet<String>> = Mutex::new(HashSet::new());
+    }
+
+    /// Database created for a single test
+    #[derive(Debug, Clone)]
+    pub struct TestDatabase {
+        name: String,
+    }
+
+    impl TestDatabase {
+        pub fn name(&self) -> &str {
+            &self.name
+        }
+
+        pub fn create_pg_deadpool(&self) -> deadpool_postgres::Pool {
+            connection::create_pg_deadpool_for(&self.name)
+        }
+
+        pub async fn create_sqlx_pool(&self) -> sqlx::PgPool {
+            connection::create_sqlx_pool_for(&self.name).await
+        }
+    }
+
+    /// Name of the template built from the schema, derived from its FNV-1a hash
+    fn template_name(schema: &str) -> String {
+        let hash = schema
+            .bytes()
+            .fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| {
+                (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+            });
+        format!("test_template_{hash:016x}")
+    }
+
+    async fn terminate_connections(admin: &deadpool_postgres::Client, database: &str) {
+        admin
+            .execute(
+                "SELECT pg_terminate_backend(pid) FROM pg_stat_activity WHERE datname = $1",
+                &[&database],
+            )
+            .await
+            .unwrap();
+    }
+
+    /// Builds the template database of the schema, once for all the test processes.
+    async fn ensure_template(admin: &deadpool_postgres::Client, schema: &str) -> String {
+        let template = template_name(schema);
+        if TEMPLATES.lock().unwrap().contains(&template) {
+            return template;
+        }
+
+        // NOTE: serializes the test processes building the same template
+        admin
+            .execute("SELECT pg_advisory_lock(hashtext($1))", &[&template])
+            .await
+            .unwrap();
+
+        let exists = admin
+            .query_opt("SELECT 1 FROM pg_database WHERE datname = $1", &[&template])
+            .await
+            .unwrap()
+            .is_some();
+
+        if !exists {
+            // built under another name and renamed once complete, so a failed build is never
+            // used as template
+            let build = format!("{template}_build");
+            admin
+                .batch_execute(&format!("DROP DATABASE IF EXISTS \"{build}\" WITH (FORCE)"))
+                .await
+                .unwrap();
+            admin
+                .batch_execute(&format!("CREATE DATABASE \"{build}\""))
+                .await
+                .unwrap();
+
+            let pool = connection::create_pg_deadpool_for(&build);
+            pool.get()
+                .await
+                .unwrap()
+                .batch_execute(schema)
+                .await
+                .unwrap();
+            drop(pool);
+
+            terminate_connections(admin, &build).await;
+            admin
+                .batch_execute(&format!(
+                    "ALTER DATABASE \"{build}\" RENAME TO \"{template}\""
+                ))
+                .await
+                .unwrap();
+        }
+
+        admin
+            .execute("SELECT pg_advisory_unlock(hashtext($1))", &[&template])
+            .await
+            .unwrap();
+
+        TEMPLATES.lock().unwrap().insert(template.clone());
+        template
+    }
+
+    /// Runs the `test` on a database of its own, created from a template built with the
+    /// `schema`, e.g. `include_str!("dbschema.sql")`.
+    ///
+    /// The database is named `test_<uuid>` and is dropped when the test ends, even if it
+    /// panics, the panic being resumed after. The template is built once and reused while the
+    /// schema doesn't change.
+    pub async fn with_test_database<F, Fut, T>(schema: &str, test: F) -> T
+    where
+        F: FnOnce(TestDatabase) -> Fut,
+        Fut: Future<Output = T>,
+    {
+        let admin_pool = connection::create_pg_deadpool();
+        let admin = admin_pool.get().await.unwrap();
+
+        let template = ensure_template(&admin, schema).await;
+
+        let name = format!("test_{}", uuid::Uuid::new_v4().simple());
+        admin
+            .batch_execute(&format!(
+                "CREATE DATABASE \"{name}\" TEMPLATE \"{template}\""
+            ))
+            .await
+            .unwrap();
+
+        let res = AssertUnwindSafe(test(TestDatabase { name: name.clone() }))
+            .catch_unwind()
+            .await;
+
+        admin
+            .batch_execute(&format!("DROP DATABASE IF EXISTS \"{name}\" WITH (FORCE)"))
+            .await
+            .unwrap();
+
+        match res {
+            Ok(output) => output,
+            Err(panic) => panic::resume_unwind(panic),
+        }
+    }
+}